@@ -0,0 +1,11 @@
+CREATE SCHEMA IF NOT EXISTS accs;
+
+CREATE TABLE IF NOT EXISTS accs.market (
+    id bigint NOT NULL,
+    category bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    description text NOT NULL,
+    quantity bigint NOT NULL,
+    price double precision NOT NULL,
+    PRIMARY KEY (id, "time")
+);
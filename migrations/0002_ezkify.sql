@@ -0,0 +1,22 @@
+CREATE SCHEMA IF NOT EXISTS ezkify;
+
+CREATE TABLE IF NOT EXISTS ezkify.categories (
+    key text NOT NULL,
+    id bigint NOT NULL,
+    "desc" text NOT NULL,
+    PRIMARY KEY (key, id)
+);
+
+CREATE TABLE IF NOT EXISTS ezkify.items (
+    key text NOT NULL,
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    category_id bigint NOT NULL,
+    service text NOT NULL,
+    rate_per_1k double precision NOT NULL,
+    min_order bigint NOT NULL,
+    max_order bigint NOT NULL,
+    description text NOT NULL,
+    PRIMARY KEY (key, id, "time"),
+    FOREIGN KEY (key, category_id) REFERENCES ezkify.categories(key, id)
+);
@@ -0,0 +1,20 @@
+CREATE SCHEMA IF NOT EXISTS blackhatworld;
+
+CREATE TABLE IF NOT EXISTS blackhatworld.content (
+    id bigint NOT NULL,
+    content text NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS blackhatworld.posts (
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    author text NOT NULL,
+    title text NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    replies bigint NOT NULL,
+    views bigint NOT NULL,
+    last_reply timestamp without time zone NOT NULL,
+    section bigint NOT NULL,
+    PRIMARY KEY (id)
+);
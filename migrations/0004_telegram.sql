@@ -0,0 +1,57 @@
+CREATE SCHEMA IF NOT EXISTS telegram;
+
+CREATE TABLE IF NOT EXISTS telegram.channel (
+    id bigint NOT NULL,
+    name text NOT NULL,
+    min_message_id integer NOT NULL,
+    max_message_id integer NOT NULL,
+    access_hash bigint NOT NULL,
+    last_fetch timestamp without time zone NOT NULL,
+    app_id integer NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS telegram.message (
+    id bigint NOT NULL,
+    message_id integer NOT NULL,
+    channel_id bigint NOT NULL,
+    data jsonb NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS telegram.link (
+    c1 bigint NOT NULL,
+    message_id integer NOT NULL,
+    c2 bigint NOT NULL,
+    PRIMARY KEY (c1, message_id, c2)
+);
+
+CREATE TABLE IF NOT EXISTS telegram.invite (
+    hash text NOT NULL,
+    channel_id bigint NOT NULL,
+    type "char" NOT NULL,
+    description text NOT NULL,
+    PRIMARY KEY (hash)
+);
+
+CREATE TABLE IF NOT EXISTS telegram.bots (
+    id bigint NOT NULL,
+    name text NOT NULL,
+    access_hash bigint NOT NULL,
+    app_id integer NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS telegram.interaction (
+    bot_id bigint NOT NULL,
+    message_id integer NOT NULL,
+    request text NOT NULL,
+    response jsonb NOT NULL,
+    PRIMARY KEY (bot_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS channel_lower_idx ON telegram.channel (lower(name));
+
+CREATE INDEX IF NOT EXISTS message_channel_id_message_id_idx ON telegram.message (channel_id, message_id);
+
+CREATE INDEX IF NOT EXISTS bots_lower_idx ON telegram.bots (lower(name));
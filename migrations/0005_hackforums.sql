@@ -0,0 +1,22 @@
+CREATE SCHEMA IF NOT EXISTS hackforums;
+
+-- `blackhatworld-server` joins these two tables with NATURAL JOIN, so `id` must
+-- stay their only common column.
+CREATE TABLE IF NOT EXISTS hackforums.posts (
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    author text NOT NULL,
+    title text NOT NULL,
+    replies bigint NOT NULL,
+    views bigint NOT NULL,
+    last_reply timestamp without time zone NOT NULL,
+    section bigint NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS hackforums.content (
+    id bigint NOT NULL,
+    create_time timestamp without time zone NOT NULL,
+    content text NOT NULL,
+    PRIMARY KEY (id)
+);
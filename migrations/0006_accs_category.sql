@@ -0,0 +1,15 @@
+-- One row per `.soc-title` heading of the index page; the listings of the
+-- `socs` block under it are the rows of accs.market with this `category`.
+CREATE TABLE accs.category (
+    id bigint NOT NULL,
+    description text NOT NULL,
+    "position" integer NOT NULL,
+    first_seen timestamp without time zone NOT NULL,
+    last_seen timestamp without time zone NOT NULL,
+    PRIMARY KEY (id)
+);
+
+-- Categories missing from the latest index page.
+CREATE VIEW accs.category_gone AS
+SELECT * FROM accs.category
+WHERE last_seen < (SELECT max(last_seen) FROM accs.category);
@@ -0,0 +1,101 @@
+-- Current state of every listing. A run only updates the listings it sees;
+-- what changed is recorded in accs.listing_event by the trigger below.
+CREATE TABLE accs.listing (
+    id bigint NOT NULL,
+    category bigint NOT NULL,
+    description text NOT NULL,
+    quantity bigint NOT NULL,
+    price double precision NOT NULL,
+    first_seen timestamp without time zone NOT NULL,
+    last_seen timestamp without time zone NOT NULL,
+    gone boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX ON accs.listing (category) WHERE NOT gone;
+
+-- `price` / `quantity` are the values after the event, `old_*` those before
+-- (NULL for `appeared`).
+CREATE TABLE accs.listing_event (
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    kind text NOT NULL CHECK (kind IN ('appeared', 'price', 'quantity', 'disappeared')),
+    price double precision NOT NULL,
+    quantity bigint NOT NULL,
+    old_price double precision,
+    old_quantity bigint,
+    PRIMARY KEY (id, "time", kind)
+);
+
+CREATE INDEX ON accs.listing_event ("time");
+
+-- One row per category page fetched; `run` is the time of the index page of
+-- the same run.
+CREATE TABLE accs.scan (
+    category bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    run timestamp without time zone NOT NULL,
+    PRIMARY KEY (category, "time")
+);
+
+-- Carry the history kept so far in accs.market over.
+INSERT INTO accs.listing_event
+SELECT id, "time", kind, price, quantity, old_price, old_quantity
+FROM (
+    SELECT id, "time", price, quantity,
+        lag(price) OVER w AS old_price,
+        lag(quantity) OVER w AS old_quantity
+    FROM accs.market
+    WINDOW w AS (PARTITION BY id ORDER BY "time")
+) AS m
+CROSS JOIN LATERAL (VALUES
+    (CASE WHEN old_price IS NULL THEN 'appeared' END),
+    (CASE WHEN old_price <> price THEN 'price' END),
+    (CASE WHEN old_quantity <> quantity THEN 'quantity' END)
+) AS k(kind)
+WHERE kind IS NOT NULL;
+
+INSERT INTO accs.listing (id, category, description, quantity, price, first_seen, last_seen)
+SELECT DISTINCT ON (id) id, category, description, quantity, price,
+    min("time") OVER (PARTITION BY id), "time"
+FROM accs.market
+ORDER BY id, "time" DESC;
+
+CREATE FUNCTION accs.listing_event() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO accs.listing_event VALUES (NEW.id, NEW.first_seen, 'appeared', NEW.price, NEW.quantity, NULL, NULL);
+    ELSIF OLD.gone AND NOT NEW.gone THEN
+        INSERT INTO accs.listing_event VALUES (NEW.id, NEW.last_seen, 'appeared', NEW.price, NEW.quantity, NULL, NULL);
+    ELSIF NOT NEW.gone THEN
+        IF NEW.price <> OLD.price THEN
+            INSERT INTO accs.listing_event VALUES (NEW.id, NEW.last_seen, 'price', NEW.price, NEW.quantity, OLD.price, OLD.quantity);
+        END IF;
+        IF NEW.quantity <> OLD.quantity THEN
+            INSERT INTO accs.listing_event VALUES (NEW.id, NEW.last_seen, 'quantity', NEW.price, NEW.quantity, OLD.price, OLD.quantity);
+        END IF;
+    END IF;
+    RETURN NULL;
+END
+$$;
+
+CREATE TRIGGER listing_event AFTER INSERT OR UPDATE ON accs.listing
+FOR EACH ROW EXECUTE FUNCTION accs.listing_event();
+
+-- A category page that no longer shows a listing means it is gone.
+CREATE FUNCTION accs.scan_gone() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    WITH gone AS (
+        UPDATE accs.listing SET gone = true
+        WHERE category = NEW.category AND NOT gone AND last_seen < NEW."time"
+        RETURNING id, price, quantity
+    )
+    INSERT INTO accs.listing_event
+    SELECT id, NEW."time", 'disappeared', price, quantity, price, quantity FROM gone
+    ON CONFLICT DO NOTHING;
+    RETURN NULL;
+END
+$$;
+
+CREATE TRIGGER scan_gone AFTER INSERT ON accs.scan
+FOR EACH ROW EXECUTE FUNCTION accs.scan_gone();
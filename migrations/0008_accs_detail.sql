@@ -0,0 +1,12 @@
+-- What the detail page of a listing shows beyond accs.listing. `item_id` is
+-- accsmarket's own id of the listing, taken from its URL.
+CREATE TABLE accs.listing_detail (
+    id bigint NOT NULL REFERENCES accs.listing (id) ON UPDATE CASCADE,
+    item_id bigint,
+    url text NOT NULL,
+    seller text,
+    warranty text,
+    format text,
+    "time" timestamp without time zone NOT NULL,
+    PRIMARY KEY (id)
+);
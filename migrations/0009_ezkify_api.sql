@@ -0,0 +1,3 @@
+-- What the reseller API says besides the services page; null for scraped pages.
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS refill boolean;
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS cancel boolean;
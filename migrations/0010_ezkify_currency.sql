@@ -0,0 +1,9 @@
+-- The rate as shown by the panel; rate_per_1k is now converted to US dollars.
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS rate double precision;
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS currency text;
+
+-- only `$` rates were parsed before
+UPDATE ezkify.items SET rate = rate_per_1k, currency = 'USD' WHERE currency IS NULL;
+
+ALTER TABLE ezkify.items ALTER COLUMN rate SET NOT NULL;
+ALTER TABLE ezkify.items ALTER COLUMN currency SET NOT NULL;
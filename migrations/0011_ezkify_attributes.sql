@@ -0,0 +1,4 @@
+-- Read from the columns of the panel or the description; null where it does not say.
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS refill_days integer;
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS drip_feed boolean;
+ALTER TABLE ezkify.items ADD COLUMN IF NOT EXISTS average_seconds bigint;
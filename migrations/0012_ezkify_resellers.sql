@@ -0,0 +1,16 @@
+-- Items that look resold from another panel, found by `ezkify resellers`: the
+-- item (key, id, "time") of ezkify.items is the same service as the upstream
+-- one, the cheapest of its cluster, sold at `markup` times its rate.
+CREATE TABLE IF NOT EXISTS ezkify.resellers (
+    key text NOT NULL,
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    upstream_key text NOT NULL,
+    upstream_id bigint NOT NULL,
+    upstream_time timestamp without time zone NOT NULL,
+    similarity double precision NOT NULL,
+    markup double precision NOT NULL,
+    PRIMARY KEY (key, id, "time")
+);
+
+CREATE INDEX IF NOT EXISTS resellers_upstream ON ezkify.resellers (upstream_key, key);
@@ -0,0 +1,103 @@
+CREATE INDEX IF NOT EXISTS items_key_time ON ezkify.items (key, "time");
+
+-- Current state of every service of a panel, as of its latest snapshot.
+CREATE TABLE IF NOT EXISTS ezkify.service (
+    key text NOT NULL,
+    id bigint NOT NULL,
+    service text NOT NULL,
+    rate double precision NOT NULL,
+    currency text NOT NULL,
+    first_seen timestamp without time zone NOT NULL,
+    last_seen timestamp without time zone NOT NULL,
+    gone boolean NOT NULL DEFAULT false,
+    PRIMARY KEY (key, id)
+);
+
+-- How a snapshot differs from the previous one of the same panel. `service`,
+-- `rate` and `currency` are the values after the event (before it for
+-- `removed`), `old_*` those before (NULL for `added`). Rates are as shown by
+-- the panel, so a new FX table is not a price change.
+CREATE TABLE IF NOT EXISTS ezkify.service_event (
+    key text NOT NULL,
+    id bigint NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    kind text NOT NULL CHECK (kind IN ('added', 'removed', 'renamed', 'price')),
+    service text NOT NULL,
+    rate double precision NOT NULL,
+    currency text NOT NULL,
+    old_service text,
+    old_rate double precision,
+    old_currency text,
+    PRIMARY KEY (key, id, "time", kind)
+);
+
+CREATE INDEX IF NOT EXISTS service_event_key_time ON ezkify.service_event (key, "time");
+
+-- One row per snapshot stored, i.e. per services page (or API answer) with
+-- items; its items are all in ezkify.items at `time`.
+CREATE TABLE IF NOT EXISTS ezkify.snapshot (
+    key text NOT NULL,
+    "time" timestamp without time zone NOT NULL,
+    PRIMARY KEY (key, "time")
+);
+
+-- Recomputes the events of the snapshot `t` of the panel `k` against the
+-- snapshot before it; every item is `added` in the first one.
+CREATE OR REPLACE FUNCTION ezkify.snapshot_events(k text, t timestamp without time zone) RETURNS void LANGUAGE plpgsql AS $$
+DECLARE
+    prev timestamp without time zone;
+BEGIN
+    SELECT max("time") INTO prev FROM ezkify.snapshot WHERE key = k AND "time" < t;
+
+    DELETE FROM ezkify.service_event WHERE key = k AND "time" = t;
+    INSERT INTO ezkify.service_event
+    SELECT k, id, t, kind,
+        coalesce(c.service, o.service), coalesce(c.rate, o.rate), coalesce(c.currency, o.currency),
+        o.service, o.rate, o.currency
+    FROM (SELECT id, service, rate, currency FROM ezkify.items WHERE key = k AND "time" = t) AS c
+    FULL JOIN (SELECT id, service, rate, currency FROM ezkify.items WHERE key = k AND "time" = prev) AS o USING (id)
+    CROSS JOIN LATERAL (VALUES
+        (CASE WHEN o.id IS NULL THEN 'added' END),
+        (CASE WHEN c.id IS NULL THEN 'removed' END),
+        (CASE WHEN c.service <> o.service THEN 'renamed' END),
+        (CASE WHEN c.rate <> o.rate OR c.currency <> o.currency THEN 'price' END)
+    ) AS e(kind)
+    WHERE kind IS NOT NULL;
+END
+$$;
+
+-- Runs for new snapshots and for replayed ones, which may be older than what
+-- is stored: the events of the snapshot after them are then recomputed too,
+-- and `ezkify.service` keeps the state of the latest snapshot.
+CREATE OR REPLACE FUNCTION ezkify.snapshot_diff() RETURNS trigger LANGUAGE plpgsql AS $$
+DECLARE
+    later timestamp without time zone;
+    latest timestamp without time zone;
+BEGIN
+    PERFORM ezkify.snapshot_events(NEW.key, NEW."time");
+    SELECT min("time") INTO later FROM ezkify.snapshot WHERE key = NEW.key AND "time" > NEW."time";
+    IF later IS NOT NULL THEN
+        PERFORM ezkify.snapshot_events(NEW.key, later);
+    END IF;
+
+    INSERT INTO ezkify.service AS s (key, id, service, rate, currency, first_seen, last_seen)
+    SELECT key, id, service, rate, currency, "time", "time" FROM ezkify.items WHERE key = NEW.key AND "time" = NEW."time"
+    ON CONFLICT (key, id) DO UPDATE SET
+        service = CASE WHEN excluded.last_seen >= s.last_seen THEN excluded.service ELSE s.service END,
+        rate = CASE WHEN excluded.last_seen >= s.last_seen THEN excluded.rate ELSE s.rate END,
+        currency = CASE WHEN excluded.last_seen >= s.last_seen THEN excluded.currency ELSE s.currency END,
+        first_seen = least(s.first_seen, excluded.first_seen),
+        last_seen = greatest(s.last_seen, excluded.last_seen);
+
+    SELECT max("time") INTO latest FROM ezkify.snapshot WHERE key = NEW.key;
+    UPDATE ezkify.service SET gone = last_seen < latest WHERE key = NEW.key AND gone <> (last_seen < latest);
+    RETURN NULL;
+END
+$$;
+
+CREATE OR REPLACE TRIGGER snapshot_diff AFTER INSERT OR UPDATE ON ezkify.snapshot
+FOR EACH ROW EXECUTE FUNCTION ezkify.snapshot_diff();
+
+-- Carry the snapshots stored so far over, oldest first, which also fills the
+-- tables above.
+INSERT INTO ezkify.snapshot SELECT DISTINCT key, "time" FROM ezkify.items ORDER BY "time" ON CONFLICT DO NOTHING;
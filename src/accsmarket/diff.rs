@@ -0,0 +1,68 @@
+use std::time::SystemTime;
+
+use uscr::db::get_connection;
+
+/// Prints how `accs.listing` changed from the run `from` to the run `to`,
+/// counting back from the latest run (0).
+pub async fn diff(from: usize, to: usize) -> anyhow::Result<()> {
+    const RUNS: &str = "select run, max(\"time\") from accs.scan group by run order by run desc";
+    const EVENTS: &str = "select e.kind, e.id, l.category, e.price, e.quantity, e.old_price, e.old_quantity, l.description \
+        from accs.listing_event as e join accs.listing as l using (id) \
+        where e.\"time\" > $1 and e.\"time\" <= $2 order by e.\"time\", e.id, e.kind";
+
+    anyhow::ensure!(from > to, "run {from} is not older than run {to}");
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(RUNS.into()).await?;
+    let runs = conn.query(&stmt, &[]).await?;
+    let run = |n: usize| -> anyhow::Result<(SystemTime, SystemTime)> {
+        let row = runs
+            .get(n)
+            .ok_or_else(|| anyhow::anyhow!("no run {n}, there are {} runs", runs.len()))?;
+        Ok((row.try_get(0)?, row.try_get(1)?))
+    };
+    let (from_run, from_end) = run(from)?;
+    let (to_run, to_end) = run(to)?;
+
+    println!(
+        "changes from the run at {} to the run at {}",
+        httpdate::fmt_http_date(from_run),
+        httpdate::fmt_http_date(to_run),
+    );
+
+    let stmt = conn.prepare_static(EVENTS.into()).await?;
+    let rows = conn.query(&stmt, &[&from_end, &to_end]).await?;
+    let (mut appeared, mut disappeared, mut price, mut quantity) = (0, 0, 0, 0);
+    for row in rows {
+        let id: i64 = row.try_get(1)?;
+        let category: i64 = row.try_get(2)?;
+        let new_price: f64 = row.try_get(3)?;
+        let new_quantity: i64 = row.try_get(4)?;
+        let old_price: Option<f64> = row.try_get(5)?;
+        let old_quantity: Option<i64> = row.try_get(6)?;
+        let description: &str = row.try_get(7)?;
+
+        match row.try_get(0)? {
+            "appeared" => {
+                appeared += 1;
+                println!("\x1b[32m+\x1b[0m {id} [{category}] {new_price} x {new_quantity} {description:?}");
+            }
+            "disappeared" => {
+                disappeared += 1;
+                println!("\x1b[31m-\x1b[0m {id} [{category}] {new_price} x {new_quantity} {description:?}");
+            }
+            "price" => {
+                price += 1;
+                println!("\x1b[33m$\x1b[0m {id} [{category}] {} -> {new_price} {description:?}", old_price.unwrap_or_default());
+            }
+            "quantity" => {
+                quantity += 1;
+                println!("\x1b[36m#\x1b[0m {id} [{category}] {} -> {new_quantity} {description:?}", old_quantity.unwrap_or_default());
+            }
+            kind => tracing::warn!(target: "diff", "unknown event {kind:?}"),
+        }
+    }
+
+    println!("{appeared} appeared, {disappeared} disappeared, {price} price and {quantity} quantity changes");
+    Ok(())
+}
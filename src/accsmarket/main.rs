@@ -1,52 +1,147 @@
-#![feature(try_blocks)]
-
+mod diff;
+mod replay;
 mod scrape;
+#[cfg(test)]
+mod tests;
+
+#[derive(clap::Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+    #[command(flatten)]
+    sink: uscr::sink::SinkArgs,
+    #[command(flatten)]
+    rate: uscr::scrape::limit::RateArgs,
+    #[command(flatten)]
+    archive: uscr::scrape::archive::ArchiveArgs,
+    #[arg(long, value_name = "dir", help = "Parse the pages archived in this directory instead of fetching")]
+    replay: Option<std::path::PathBuf>,
+    #[arg(long, help = "Do not follow the listings to their detail pages")]
+    no_details: bool,
+    #[arg(long, default_value = "https://accsmarket.com/", value_name = "url", help = "The site to scrape, e.g. a mirror or a local fixture server")]
+    base: reqwest::Url,
+    #[arg(short, long = "category", value_name = "id", help = "Only scrape this category (repeatable), default all")]
+    categories: Vec<i64>,
+    #[arg(long, default_value = "byPrice", value_name = "mode", help = "The sort mode sent to soc.php")]
+    sort: String,
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "n", help = "Pages fetched at the same time, still under --rate")]
+    concurrency: usize,
+    #[arg(short = 'n', long, help = "Print the parsed records as JSON instead of storing them (same as --sink stdout)")]
+    dry_run: bool,
+}
+
+#[derive(clap::Subcommand)]
+enum Commands {
+    /// Report what changed between two runs, from the database
+    Diff {
+        #[arg(default_value_t = 1, help = "The older run, counting back from the latest (0)")]
+        from: usize,
+        #[arg(default_value_t = 0, help = "The newer run")]
+        to: usize,
+    },
+}
+
+/// The URLs scraped, under `--base`, and the categories wanted.
+pub struct Site {
+    pub index: reqwest::Url,
+    pub soc: reqwest::Url,
+    pub categories: Vec<i64>,
+}
+
+impl Site {
+    /// Whether the category `id` passes the `--category` allow-list.
+    #[must_use]
+    pub fn wants(&self, id: i64) -> bool {
+        self.categories.is_empty() || self.categories.contains(&id)
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    use clap::Parser;
+    use uscr::scrape::{
+        limit::{Limiter, Policy, host_key},
+        pipeline::{Parser as _, Pipeline, Requests, fetch},
+        retry::Retry,
+    };
+    use uscr::sink::Sink;
+
     pretty_env_logger::init_timed();
-    uscr::db::init_db().await;
 
-    let client = uscr::scrape::simple();
+    let args = Args::parse();
+    if let Some(Commands::Diff { from, to }) = args.command {
+        uscr::db::init_db(args.db.config()?).await?;
+        return diff::diff(from, to).await;
+    }
+    let sink = if args.dry_run {
+        uscr::sink::AnySink::Stdout(uscr::sink::Stdout)
+    } else {
+        args.sink.open(&args.db).await?
+    };
 
-    let res = client
-        .get("https://accsmarket.com/")
-        .send()
-        .await?
-        .text()
-        .await?;
-    let html = scraper::Html::parse_document(&res);
-
-    let container = html
-        .select(&scraper::Selector::parse(".soc-bl").unwrap())
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("element not found"))?;
-
-    let ctx = scrape::Context {
-        client,
-        sel_scp: scraper::Selector::parse(".soc-text>p").unwrap(),
+    let site = Site {
+        soc: args.base.join("req/soc.php")?,
+        index: args.base,
+        categories: args.categories,
     };
+    let parser = scrape::ListingParser::default();
 
-    let sel_h2 = scraper::Selector::parse("h2").unwrap();
-    let mut id = 0;
-    let mut desc = String::new();
-    let mut futs = Vec::new();
-    for child in container.child_elements() {
-        match child.attr("class") {
-            Some("soc-title") => if let Some(h2) = child.select(&sel_h2).next() {
-                id = h2.attr("data-id").and_then(|x| x.parse().ok()).unwrap_or(0);
-                desc = h2.text().map(str::trim).collect();
-            },
-            Some("socs") => futs.push(scrape::work(id, core::mem::take(&mut desc), &ctx)),
-            e => tracing::warn!(target: "soc-bl", "Unknown class: {e:?}"),
+    if let Some(ref dir) = args.replay {
+        let entries = uscr::scrape::archive::entries(dir)?;
+        Pipeline::new("replay", &sink).run(replay::index(dir, entries.clone(), &site), &scrape::IndexParser).await;
+        Pipeline::new("replay", &sink).run(replay::socs(dir, entries.clone(), &site), &parser).await;
+        if !args.no_details {
+            let links = core::mem::take(&mut *parser.links.lock());
+            Pipeline::new("replay", &sink).run(replay::details(dir, entries, &site, links), &scrape::DetailParser).await;
         }
+        return Ok(());
     }
 
-    for fut in futs {
-        fut.await;
-        tokio::time::sleep(const { core::time::Duration::from_millis(250) }).await;
+    let client = uscr::scrape::simple();
+    let limiter = Limiter::new(
+        &[(host_key(&site.index), Policy::delay(const { core::time::Duration::from_millis(250) }))],
+        args.rate.rates,
+    );
+    let archive = args.archive.open("accsmarket")?;
+    let retry = Retry::new(Some(&limiter)).archive(archive.as_ref());
+    let res = fetch(&retry, (), client.get(site.index.clone())).await?;
+    let categories = scrape::IndexParser.parse(&res)?;
+    match sink.write(&categories).await {
+        Ok(upserted) => tracing::info!(target: "soc-bl", "\x1b[36m{} categories, {} new\x1b[0m", categories.len(), upserted.inserted),
+        Err(e) => tracing::error!(target: "soc-bl", "\x1b[31mstore err: {e:?}\x1b[0m"),
+    }
+
+    let mut source = Requests::new(&retry).concurrency(args.concurrency);
+    for category in categories.into_iter().filter(|c| site.wants(c.id)) {
+        let form = [
+            ("section", "get_soc"),
+            ("cat_id", &category.id.to_string()),
+            ("sort", &args.sort),
+        ];
+        let request = client.post(site.soc.clone()).form(&form);
+        let tag = scrape::Soc {
+            category: category.id,
+            description: category.description,
+            run: res.date,
+        };
+        source.push(tag, request);
+    }
+
+    Pipeline::new("worker", &sink).run(source, &parser).await;
+
+    if !args.no_details {
+        let links = core::mem::take(&mut *parser.links.lock());
+        let mut details = Requests::new(&retry).concurrency(args.concurrency);
+        for (id, href) in links {
+            match site.index.join(&href) {
+                Ok(url) => details.push((id, url.to_string()), client.get(url)),
+                Err(e) => tracing::warn!(target: "detail", "bad link {href:?}: {e}"),
+            }
+        }
+        Pipeline::new("detail", &sink).run(details, &scrape::DetailParser).await;
     }
-    // futures_util::future::join_all(futs).await;
 
     Ok(())
 }
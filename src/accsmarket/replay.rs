@@ -0,0 +1,76 @@
+//! The pages of an archive, as the sources the live scrape would fetch.
+
+use std::path::Path;
+
+use hashbrown::HashMap;
+use uscr::scrape::{
+    archive::{Archived, Entry, Replay},
+    pipeline::{Fetched, Parser, Source},
+};
+
+use crate::{
+    Site,
+    scrape::{IndexParser, Soc},
+};
+
+/// The index pages.
+pub fn index<'a>(dir: &Path, entries: Vec<Entry>, site: &'a Site) -> impl Source<Page = Fetched<()>> + 'a {
+    Replay::new(dir, entries, |page: Archived| {
+        (page.entry.url == site.index.as_str() && page.entry.status == Some(200))
+            .then(|| Ok(Fetched { tag: (), date: page.date(), body: page.body }))
+    })
+}
+
+/// The category pages, described by the index page fetched before them.
+pub fn socs<'a>(dir: &Path, entries: Vec<Entry>, site: &'a Site) -> impl Source<Page = Fetched<Soc>> + 'a {
+    let mut descs = HashMap::<i64, String>::new();
+    let mut run = None;
+    Replay::new(dir, entries, move |page: Archived| {
+        if page.entry.status != Some(200) {
+            return None;
+        }
+        if page.entry.url == site.index.as_str() {
+            let index = Fetched { tag: (), date: page.date(), body: page.body };
+            if let Ok(categories) = IndexParser.parse(&index) {
+                descs.extend(categories.into_iter().map(|c| (c.id, c.description)));
+            }
+            run = Some(index.date);
+            return None;
+        }
+        if page.entry.url != site.soc.as_str() {
+            return None;
+        }
+        let id = str::from_utf8(&page.request)
+            .ok()?
+            .split('&')
+            .find_map(|kv| kv.strip_prefix("cat_id="))?
+            .parse()
+            .ok()?;
+        if !site.wants(id) {
+            return None;
+        }
+        let tag = Soc {
+            category: id,
+            description: descs.get(&id).cloned().unwrap_or_default(),
+            run: run.unwrap_or_else(|| page.date()),
+        };
+        Some(Ok(Fetched { tag, date: page.date(), body: page.body }))
+    })
+}
+
+/// The detail pages, for the `links` found on the category pages.
+pub fn details(
+    dir: &Path,
+    entries: Vec<Entry>,
+    site: &Site,
+    links: Vec<(i64, String)>,
+) -> impl Source<Page = Fetched<(i64, String)>> {
+    let ids = links
+        .into_iter()
+        .filter_map(|(id, href)| Some((site.index.join(&href).ok()?.to_string(), id)))
+        .collect::<HashMap<_, _>>();
+    Replay::new(dir, entries, move |page: Archived| {
+        let id = *ids.get(&page.entry.url)?;
+        (page.entry.status == Some(200)).then(|| Ok(Fetched { tag: (id, page.entry.url.clone()), date: page.date(), body: page.body }))
+    })
+}
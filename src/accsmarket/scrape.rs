@@ -1,97 +1,350 @@
-use std::{
-    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
-    time::SystemTime,
-};
+use std::time::SystemTime;
 
-use reqwest::{Client as Request, header::DATE};
+use hashbrown::HashSet;
+use parking_lot::Mutex;
 use scraper::{Html, Selector};
-use uscr::db::{BB8Error, ToSqlIter, get_connection};
-
-pub struct Context {
-    pub client: Request,
-    pub sel_scp: Selector,
-}
-
-pub async fn work(id: i64, c_desc: String, ctx: &Context) {
-    tracing::info!(target: "worker", "id = {id}, desc = {c_desc:?}");
-
-    let form = [
-        ("section", "get_soc"),
-        ("cat_id", &id.to_string()),
-        ("sort", "byPrice"),
-    ];
-
-    let request = ctx
-        .client
-        .post("https://accsmarket.com/req/soc.php")
-        .form(&form);
-
-    let res: Result<(String, SystemTime), reqwest::Error> = try {
-        let res = request.send().await?;
-        let Some(date) = res
-            .headers()
-            .get(DATE)
-            .and_then(|s| s.to_str().ok())
-            .and_then(|s| httpdate::parse_http_date(s).ok())
-        else {
-            tracing::warn!(target: "worker", "[#{id}] no/wrong date");
-            return;
+use serde::Serialize;
+use tokio_postgres::types::Type;
+use uscr::{
+    db::bulk::Bulk,
+    scrape::pipeline::{Fetched, Parser},
+    sink::Record,
+    util::stable_id,
+};
+
+/// The current state of a listing; changes are logged to
+/// `accs.listing_event` by the database.
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    pub id: i64,
+    pub category: i64,
+    pub description: String,
+    pub quantity: i64,
+    pub price: f64,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub first_seen: SystemTime,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub last_seen: SystemTime,
+}
+
+impl Record for Listing {
+    const TABLE: &'static str = "accs.listing";
+    const KEY: &'static [&'static str] = &["id"];
+    const KEEP: &'static [&'static str] = &["first_seen"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("category", Type::INT8, |x| x.category)
+            .column("description", Type::TEXT, |x| &*x.description)
+            .column("quantity", Type::INT8, |x| x.quantity)
+            .column("price", Type::FLOAT8, |x| x.price)
+            .column("first_seen", Type::TIMESTAMP, |x| x.first_seen)
+            .column("last_seen", Type::TIMESTAMP, |x| x.last_seen)
+            // replayed pages may be older than what is stored
+            .on_conflict_set(&["id"], &[
+                ("category", "case when excluded.last_seen >= accs.listing.last_seen then excluded.category else accs.listing.category end"),
+                ("quantity", "case when excluded.last_seen >= accs.listing.last_seen then excluded.quantity else accs.listing.quantity end"),
+                ("price", "case when excluded.last_seen >= accs.listing.last_seen then excluded.price else accs.listing.price end"),
+                ("gone", "accs.listing.gone and excluded.last_seen <= accs.listing.last_seen"),
+                ("first_seen", "least(accs.listing.first_seen, excluded.first_seen)"),
+                ("last_seen", "greatest(accs.listing.last_seen, excluded.last_seen)"),
+            ])
+    }
+}
+
+/// What the detail page of a [`Listing`] adds to it.
+#[derive(Debug, Serialize)]
+pub struct Detail {
+    /// The [`Listing`] id.
+    pub id: i64,
+    /// accsmarket's own id of the listing, from the URL.
+    pub item_id: Option<i64>,
+    pub url: String,
+    pub seller: Option<String>,
+    pub warranty: Option<String>,
+    pub format: Option<String>,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub time: SystemTime,
+}
+
+impl Record for Detail {
+    const TABLE: &'static str = "accs.listing_detail";
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("item_id", Type::INT8, |x| x.item_id)
+            .column("url", Type::TEXT, |x| &*x.url)
+            .column("seller", Type::TEXT, |x| x.seller.as_deref())
+            .column("warranty", Type::TEXT, |x| x.warranty.as_deref())
+            .column("format", Type::TEXT, |x| x.format.as_deref())
+            .column("time", Type::TIMESTAMP, |x| x.time)
+            .on_conflict_update(&["id"], &["item_id", "url", "seller", "warranty", "format", "time"])
+    }
+}
+
+/// A category page that was fetched, telling the database which listings of
+/// the category are gone.
+#[derive(Debug, Serialize)]
+pub struct Scan {
+    pub category: i64,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub time: SystemTime,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub run: SystemTime,
+}
+
+impl Record for Scan {
+    const TABLE: &'static str = "accs.scan";
+    const KEY: &'static [&'static str] = &["category", "time"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("category", Type::INT8, |x| x.category)
+            .column("time", Type::TIMESTAMP, |x| x.time)
+            .column("run", Type::TIMESTAMP, |x| x.run)
+            .on_conflict_nothing(&["category", "time"])
+    }
+}
+
+/// The category a page is fetched for, and the time of the index page of the run.
+#[derive(Debug)]
+pub struct Soc {
+    pub category: i64,
+    pub description: String,
+    pub run: SystemTime,
+}
+
+/// A `.soc-title` heading of the index page, naming the `socs` block of
+/// listings after it.
+#[derive(Debug, Serialize)]
+pub struct Category {
+    pub id: i64,
+    pub description: String,
+    /// Order on the index page.
+    pub position: i32,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub first_seen: SystemTime,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    pub last_seen: SystemTime,
+}
+
+impl Record for Category {
+    const TABLE: &'static str = "accs.category";
+    const KEY: &'static [&'static str] = &["id"];
+    const KEEP: &'static [&'static str] = &["first_seen"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("description", Type::TEXT, |x| &*x.description)
+            .column("position", Type::INT4, |x| x.position)
+            .column("first_seen", Type::TIMESTAMP, |x| x.first_seen)
+            .column("last_seen", Type::TIMESTAMP, |x| x.last_seen)
+            // replayed pages may be older than what is stored
+            .on_conflict_set(&["id"], &[
+                ("description", "case when excluded.last_seen >= accs.category.last_seen then excluded.description else accs.category.description end"),
+                ("position", "case when excluded.last_seen >= accs.category.last_seen then excluded.position else accs.category.position end"),
+                ("first_seen", "least(accs.category.first_seen, excluded.first_seen)"),
+                ("last_seen", "greatest(accs.category.last_seen, excluded.last_seen)"),
+            ])
+    }
+}
+
+/// The categories of the index page `body` that have a `socs` block.
+pub fn categories(body: &str, date: SystemTime) -> anyhow::Result<Vec<Category>> {
+    let html = Html::parse_document(body);
+    let container = html
+        .select(&Selector::parse(".soc-bl").unwrap())
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("element not found"))?;
+
+    let sel_h2 = Selector::parse("h2").unwrap();
+    let mut id = 0;
+    let mut desc = String::new();
+    let mut categories = Vec::new();
+    for child in container.child_elements() {
+        match child.attr("class") {
+            Some("soc-title") => if let Some(h2) = child.select(&sel_h2).next() {
+                id = h2.attr("data-id").and_then(|x| x.parse().ok()).unwrap_or(0);
+                desc = h2.text().map(str::trim).collect();
+            },
+            Some("socs") => categories.push(Category {
+                id,
+                description: core::mem::take(&mut desc),
+                position: categories.len().try_into()?,
+                first_seen: date,
+                last_seen: date,
+            }),
+            e => tracing::warn!(target: "soc-bl", "Unknown class: {e:?}"),
+        }
+    }
+
+    Ok(categories)
+}
+
+/// What a category page holds.
+#[derive(Debug)]
+pub struct SocPage {
+    pub listings: Vec<Listing>,
+    /// [`None`] if the page is empty (e.g. an error page), which must not
+    /// mark everything gone.
+    pub scan: Option<Scan>,
+    /// The links to the detail pages, as `(listing id, href)`.
+    pub links: Vec<(i64, String)>,
+}
+
+/// The listings of the category page `body` fetched for `soc` at `date`.
+///
+/// Listings are identified by their description, so only the first of those
+/// with the same one is kept: a batch cannot update a row twice.
+pub fn listings(body: &str, soc: &Soc, date: SystemTime) -> SocPage {
+    let sel_scp = Selector::parse(".soc-text>p").unwrap();
+    let sel_link = Selector::parse("a[href]").unwrap();
+
+    let fragment = Html::parse_fragment(body);
+    let root = fragment.root_element();
+    let mut listings = Vec::new();
+    let mut links = Vec::new();
+    let mut ids = HashSet::new();
+    for child in root.child_elements() {
+        let quantity = child
+            .attr("data-qty")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0i64);
+        let cost = child
+            .attr("data-cost")
+            .and_then(|x| x.replace(',', ".").parse().ok())
+            .unwrap_or(0.0f64);
+        let desc = if let Some(d) = child.select(&sel_scp).next() {
+            d.text().map(str::trim).collect()
+        } else {
+            String::new()
         };
-        (res.text().await?, date)
-    };
-    let (res, date) = match res {
-        Ok(r) => r,
-        Err(e) => {
-            tracing::warn!(target: "worker", "[#{id}] err: {e:?}");
-            return;
+
+        let listing_id = stable_id(&desc);
+        if !ids.insert(listing_id) {
+            tracing::warn!(target: "worker", "id = {}, listing {desc:?} repeated, kept the first", soc.category);
+            continue;
         }
-    };
-
-    let mut archived = Vec::new();
-    {
-        let fragment = Html::parse_fragment(&res);
-        let root = fragment.root_element();
-        for child in root.child_elements() {
-            let quantity = child
-                .attr("data-qty")
-                .and_then(|x| x.parse().ok())
-                .unwrap_or(0i64);
-            let cost = child
-                .attr("data-cost")
-                .and_then(|x| x.replace(',', ".").parse().ok())
-                .unwrap_or(0.0f64);
-            let desc = if let Some(d) = child.select(&ctx.sel_scp).next() {
-                d.text().map(str::trim).collect()
-            } else {
-                String::new()
-            };
-
-            let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(&desc);
-
-            archived.push((hash.cast_signed(), desc, quantity, cost));
+        if let Some(href) = child.select(&sel_link).find_map(|a| a.attr("href")) {
+            links.push((listing_id, href.to_owned()));
         }
+
+        listings.push(Listing {
+            id: listing_id,
+            category: soc.category,
+            description: desc,
+            quantity,
+            price: cost,
+            first_seen: date,
+            last_seen: date,
+        });
     }
 
-    if !archived.is_empty() {
-        let res: Result<(), BB8Error> = try {
-            const SQL: &str = "with tmp_insert(i, d, q, p) as (select * from unnest($1::bigint[], $4::text[], $5::bigint[], $6::float8[])) insert into accs.market (id, category, time, description, quantity, price) select i, $2, $3, d, q, p from tmp_insert";
-
-            let mut conn = get_connection().await?;
-            let stmt = conn.prepare_static(SQL.into()).await?;
-            conn.execute(&stmt, &[
-                &ToSqlIter(archived.iter().map(|x| x.0)),
-                &id,
-                &date,
-                &ToSqlIter(archived.iter().map(|x| &*x.1)),
-                &ToSqlIter(archived.iter().map(|x| x.2)),
-                &ToSqlIter(archived.iter().map(|x| x.3)),
-            ])
-            .await?;
+    let scan = (!listings.is_empty()).then_some(Scan { category: soc.category, time: date, run: soc.run });
+    SocPage { listings, scan, links }
+}
 
-            tracing::info!(target: "worker", "\x1b[36m[#{id}] update {} items\x1b[0m", archived.len());
+/// The value of the field `name` of the detail page at `url`, given the
+/// distinct `values` it has there: none if it has no value or several, which
+/// are logged.
+fn field(url: &str, name: &str, mut values: Vec<String>) -> Option<String> {
+    match values.len() {
+        1 => values.pop(),
+        0 => {
+            tracing::warn!(target: "detail", "{url}: no {name}");
+            None
+        }
+        _ => {
+            tracing::warn!(target: "detail", "{url}: {name} is one of {values:?}, stored as unknown");
+            None
+        }
+    }
+}
+
+/// The detail page `body` of the listing `id` at `url`.
+///
+/// The fields are read from the `Label: value` lines of the page (list items,
+/// table rows and paragraphs) with one of the labels below. A field that is
+/// not found, or found with different values, is left `None`.
+pub fn detail(body: &str, id: i64, url: &str, date: SystemTime) -> Detail {
+    let sel_line = Selector::parse("li, tr, p").unwrap();
+    let html = Html::parse_document(body);
+
+    let (mut seller, mut warranty, mut format) = (Vec::new(), Vec::new(), Vec::new());
+    for line in html.select(&sel_line) {
+        let text = line.text().map(str::trim).filter(|x| !x.is_empty()).collect::<Vec<_>>().join(" ");
+        let Some((label, value)) = text.split_once(':') else {
+            continue;
+        };
+        let values: &mut Vec<String> = match &*label.trim().to_lowercase() {
+            "seller" | "vendor" => &mut seller,
+            "warranty" | "guarantee" => &mut warranty,
+            "format" | "account format" => &mut format,
+            _ => continue,
         };
-        if let Err(e) = res {
-            tracing::error!(target: "worker", "\x1b[31m[#{id}] {e:?}\x1b[0m");
+        let value = value.trim();
+        if !value.is_empty() && !values.iter().any(|x| x == value) {
+            values.push(value.to_owned());
         }
     }
+
+    Detail {
+        id,
+        item_id: url
+            .trim_end_matches('/')
+            .rsplit(['/', '-', '='])
+            .next()
+            .and_then(|x| x.parse().ok()),
+        url: url.to_owned(),
+        seller: field(url, "seller", seller),
+        warranty: field(url, "warranty", warranty),
+        format: field(url, "format", format),
+        time: date,
+    }
+}
+
+/// Parses the index page with [`categories`].
+pub struct IndexParser;
+
+impl Parser<Fetched<()>> for IndexParser {
+    type Output = Vec<Category>;
+
+    fn parse(&self, page: &Fetched<()>) -> anyhow::Result<Vec<Category>> {
+        categories(&page.body, page.date)
+    }
+}
+
+/// Parses a category page with [`listings`], keeping the links to the detail
+/// pages in `links`.
+#[derive(Default)]
+pub struct ListingParser {
+    pub links: Mutex<Vec<(i64, String)>>,
+}
+
+impl Parser<Fetched<Soc>> for ListingParser {
+    type Output = (Vec<Listing>, Vec<Scan>);
+
+    fn parse(&self, page: &Fetched<Soc>) -> anyhow::Result<Self::Output> {
+        tracing::info!(target: "worker", "id = {}, desc = {:?}", page.tag.category, page.tag.description);
+
+        let SocPage { listings, scan, mut links } = listings(&page.body, &page.tag, page.date);
+        self.links.lock().append(&mut links);
+        Ok((listings, scan.into_iter().collect()))
+    }
+}
+
+/// Parses the detail page of the listing tagged `(id, url)` with [`detail`].
+pub struct DetailParser;
+
+impl Parser<Fetched<(i64, String)>> for DetailParser {
+    type Output = Vec<Detail>;
+
+    fn parse(&self, page: &Fetched<(i64, String)>) -> anyhow::Result<Vec<Detail>> {
+        let (id, ref url) = page.tag;
+        Ok(vec![detail(&page.body, id, url, page.date)])
+    }
 }
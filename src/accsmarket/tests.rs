@@ -0,0 +1,103 @@
+use std::time::{Duration, SystemTime};
+
+use uscr::util::stable_id;
+
+use crate::scrape::{Soc, categories, detail, listings};
+
+const INDEX: &str = include_str!("../../fixtures/accsmarket/index.html");
+const SOC: &str = include_str!("../../fixtures/accsmarket/soc.html");
+const DETAIL: &str = include_str!("../../fixtures/accsmarket/detail.html");
+
+fn date() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000)
+}
+
+fn soc() -> Soc {
+    Soc {
+        category: 12,
+        description: "Facebook".into(),
+        run: SystemTime::UNIX_EPOCH + Duration::from_secs(1_749_999_990),
+    }
+}
+
+#[test]
+fn index() {
+    let categories = categories(INDEX, date()).unwrap();
+    let got = categories.iter().map(|c| (c.id, &*c.description, c.position)).collect::<Vec<_>>();
+    assert_eq!(got, [(12, "Facebook", 0), (7, "Instagramaccounts", 1), (0, "Other", 2)]);
+    assert!(categories.iter().all(|c| c.first_seen == date() && c.last_seen == date()));
+}
+
+#[test]
+fn index_without_container() {
+    assert!(categories("<html><body></body></html>", date()).is_err());
+}
+
+#[test]
+fn soc_listings() {
+    let page = listings(SOC, &soc(), date());
+    let got = page.listings.iter().map(|l| (&*l.description, l.quantity, l.price)).collect::<Vec<_>>();
+    assert_eq!(got, [
+        // comma decimal
+        ("FB accounts, 2FA, aged 2019", 120, 1.25),
+        ("FBfreshaccounts", 3, 0.4),
+        // unparsable quantity, missing cost
+        ("FB business manager", 0, 0.0),
+    ]);
+    assert!(page.listings.iter().all(|l| l.category == 12 && l.id == stable_id(&l.description)));
+
+    let scan = page.scan.unwrap();
+    assert_eq!((scan.category, scan.time, scan.run), (12, date(), soc().run));
+
+    assert_eq!(page.links, [
+        (stable_id("FB accounts, 2FA, aged 2019"), "/en/item/fb-aged-2019-48213".to_owned()),
+        (stable_id("FB business manager"), "/en/item/fb-bm-77".to_owned()),
+    ]);
+}
+
+#[test]
+fn soc_repeated_listing() {
+    let body = r#"<div class="soc-body" data-qty="5" data-cost="2"><div class="soc-text"><p>Gmail aged</p></div><a href="/en/item/1">Buy</a></div>
+<div class="soc-body" data-qty="9" data-cost="1"><div class="soc-text"><p>Gmail aged</p></div><a href="/en/item/2">Buy</a></div>
+<div class="soc-body" data-qty="1" data-cost="3"><div class="soc-text"><p>Gmail new</p></div></div>"#;
+    let page = listings(body, &soc(), date());
+    let got = page.listings.iter().map(|l| (&*l.description, l.quantity, l.price)).collect::<Vec<_>>();
+    assert_eq!(got, [("Gmail aged", 5, 2.0), ("Gmail new", 1, 3.0)]);
+    assert_eq!(page.links, [(stable_id("Gmail aged"), "/en/item/1".to_owned())]);
+}
+
+#[test]
+fn soc_empty() {
+    let page = listings("\n", &soc(), date());
+    assert!(page.listings.is_empty());
+    assert!(page.scan.is_none());
+}
+
+#[test]
+fn detail_fields() {
+    let url = "https://accsmarket.com/en/item/fb-aged-2019-48213";
+    let detail = detail(DETAIL, 42, url, date());
+    assert_eq!(detail.id, 42);
+    assert_eq!(detail.item_id, Some(48213));
+    assert_eq!(detail.url, url);
+    // two sellers: not a guess
+    assert_eq!(detail.seller, None);
+    assert_eq!(detail.warranty.as_deref(), Some("24 hours"));
+    assert_eq!(detail.format.as_deref(), Some("login:password:email"));
+    assert_eq!(detail.time, date());
+}
+
+#[test]
+fn detail_without_fields() {
+    let detail = detail("<p>Sold out</p>", 1, "https://accsmarket.com/en/item/sold-out/", date());
+    assert_eq!(detail.item_id, None);
+    assert!(detail.seller.is_none() && detail.warranty.is_none() && detail.format.is_none());
+}
+
+#[test]
+fn detail_repeated_field() {
+    let body = "<ul><li>Seller: acme</li><li>Vendor: acme</li></ul><p>Warranty: 1 day</p><p>Guarantee: 7 days</p>";
+    let detail = detail(body, 1, "https://accsmarket.com/en/item/x-1", date());
+    assert_eq!(detail.seller.as_deref(), Some("acme"));
+    assert_eq!(detail.warranty, None);
+}
@@ -25,6 +25,12 @@ async fn get_black_ids() -> impl Iterator<Item = i64> {
         .filter_map(|row| row.try_get(0).ok())
 }
 
+#[derive(clap::Parser)]
+struct Args {
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+}
+
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     use axum::{
@@ -33,13 +39,17 @@ async fn main() -> std::io::Result<()> {
         routing::{get, post},
         serve,
     };
+    use clap::Parser;
     use tokio::net::UnixListener;
     use tower_http::cors::CorsLayer;
 
     const SOCK: &str = "underground-scraper.sock";
 
     pretty_env_logger::init_timed();
-    uscr::db::init_db().await;
+
+    let args = Args::parse();
+    let config = args.db.config().map_err(std::io::Error::other)?;
+    uscr::db::init_db(config).await.map_err(std::io::Error::other)?;
 
     service::init(Vec::new(), get_black_ids().await.collect());
 
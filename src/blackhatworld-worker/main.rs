@@ -11,6 +11,10 @@ const PROXY_PASSWORD: Option<&str> = option_env!("PROXY_PASSWORD");
 struct Args {
     #[command(subcommand)]
     command: Commands,
+    #[command(flatten)]
+    rate: uscr::scrape::limit::RateArgs,
+    #[command(flatten)]
+    archive: uscr::scrape::archive::ArchiveArgs,
 }
 
 #[derive(clap::Subcommand)]
@@ -95,6 +99,18 @@ async fn main() -> anyhow::Result<()> {
             port: server_port,
         } => {
             let client = uscr::scrape::simple();
+            let limiter = uscr::scrape::limit::Limiter::new(
+                &[(worker::HOST, uscr::scrape::limit::Policy::Delay {
+                    min: const { core::time::Duration::from_millis(2400) },
+                    max: const { core::time::Duration::from_secs(3) },
+                })],
+                args.rate.rates,
+            );
+            let archive = args.archive.open("blackhatworld")?;
+            let retry = uscr::scrape::retry::Retry::new(Some(&limiter)).archive(archive.as_ref());
+            let gateway_retry = uscr::scrape::retry::Retry::new(None)
+                .attempts(8)
+                .backoff(const { core::time::Duration::from_secs(1) }, const { core::time::Duration::from_secs(30) });
             let file = std::fs::File::open(config)?;
             let reader = std::io::BufReader::new(file);
             let config = serde_json::from_reader::<_, WorkConfig>(reader)?;
@@ -106,6 +122,9 @@ async fn main() -> anyhow::Result<()> {
                     server_port,
                     headers,
                     gateway: client.clone(),
+                    limiter: &limiter,
+                    retry: &retry,
+                    gateway_retry: &gateway_retry,
                 })
             });
 
@@ -1,23 +1,32 @@
-use core::time::Duration;
-
 use compact_str::{CompactString, format_compact};
-use rand::random_range;
 use reqwest::{
     Client, Proxy, Version,
     header::{COOKIE, HeaderValue},
 };
 use serde::Serialize;
+use uscr::scrape::{
+    limit::Limiter,
+    retry::{Class, Retry},
+};
 
 use crate::{ConfigHeaders, PROXY_HOST, PROXY_PASSWORD, PROXY_USERNAME};
 
-pub struct Worker {
+pub const HOST: &str = "www.blackhatworld.com";
+
+pub struct Worker<'a> {
     pub client_port: u16,
     pub server_port: u16,
     pub headers: ConfigHeaders,
     pub gateway: Client,
+    /// Paced under `HOST#client_port`, each proxy on its own.
+    pub limiter: &'a Limiter,
+    /// For `HOST`, sharing `limiter`.
+    pub retry: &'a Retry<'a>,
+    /// For the local server.
+    pub gateway_retry: &'a Retry<'a>,
 }
 
-impl Worker {
+impl Worker<'_> {
     fn build_proxy(&self) -> Option<Proxy> {
         let url = format!("http://{}:{}", PROXY_HOST?, self.client_port);
         let proxy = Proxy::all(url).ok()?;
@@ -30,25 +39,20 @@ impl Worker {
         )
     }
 
-    async fn fetch_work(&self) -> reqwest::Result<Vec<i64>> {
+    async fn fetch_work(&self) -> anyhow::Result<Vec<i64>> {
         let url = format!("https://localhost:{}/get/black", self.server_port);
-        self.gateway.get(url).send().await?.json().await
+        Ok(self.gateway_retry.send(self.gateway.get(url)).await?.json().await?)
     }
 
-    async fn submit(&self, id: i64, content: &str) -> reqwest::Result<CompactString> {
+    async fn submit(&self, id: i64, content: &str) -> anyhow::Result<CompactString> {
         #[derive(Serialize)]
         struct Payload<'a> {
             id: i64,
             content: &'a str,
         }
         let url = format!("https://localhost:{}/send/black", self.server_port);
-        self.gateway
-            .post(url)
-            .json(&Payload { id, content })
-            .send()
-            .await?
-            .json()
-            .await
+        let request = self.gateway.post(url).json(&Payload { id, content });
+        Ok(self.gateway_retry.send(request).await?.json().await?)
     }
 
     fn simple_check(text: &str) -> bool {
@@ -57,7 +61,7 @@ impl Worker {
             unsafe { text.get_unchecked(..i).contains("<title>") })
     }
 
-    pub async fn into_future(mut self) -> reqwest::Result<()> {
+    pub async fn into_future(mut self) -> anyhow::Result<()> {
         let proxy = self.build_proxy();
         let mut client =
             Client::builder().connect_timeout(const { core::time::Duration::from_secs(8) });
@@ -78,63 +82,56 @@ impl Worker {
                 .user_agent(core::mem::take(&mut self.headers.user_agent))
                 .build()?;
         let target = format_compact!("worker-{}", self.client_port);
+        let key = format_compact!("{HOST}#{}", self.client_port);
 
         loop {
-            let works = loop {
-                match self.fetch_work().await {
-                    Ok(r) => break r,
-                    Err(e) => {
-                        log::error!(target: &target, "fetch work error: {e:?}");
-                        tokio::time::sleep(const { Duration::from_secs(3) }).await;
-                    }
+            let works = match self.fetch_work().await {
+                Ok(r) => r,
+                Err(e) => {
+                    log::error!(target: &target, "fetch work error, giving up: {e:?}");
+                    return Err(e);
                 }
             };
             if works.is_empty() {
                 return Ok(());
             }
             for work in works {
-                let url = format!("https://www.blackhatworld.com/seo/{work}");
+                let url = format!("https://{HOST}/seo/{work}");
                 log::info!(target: &target, "\x1b[33mscraping\x1b[0m {url} ...");
 
-                let response: reqwest::Result<String> = try {
-                    client
-                        .get(&url)
-                        .version(Version::HTTP_2)
-                        .send()
-                        .await?
-                        .text()
-                        .await?
+                let request = client.get(&url).version(Version::HTTP_2);
+                let response = match self.retry.text_as(Some(&key), request).await {
+                    Ok(page) => Ok(page.body),
+                    Err(failure) if failure.class == Class::Gone => {
+                        log::warn!(target: &target, "\x1b[35mgone\x1b[0m {url}: {failure}");
+                        continue;
+                    }
+                    Err(failure) => Err(anyhow::Error::from(failure)),
                 };
-                let sleep = match response {
+                match response {
                     Ok(text) if Self::simple_check(&text) => {
-                        loop {
-                            match self.submit(work, &text).await {
-                                Ok(result) if result.is_empty() => {
-                                    log::info!(target: &target, "\x1b[36mfinished\x1b[0m {url} ...");
-                                    break;
-                                }
-                                Ok(reason) => {
-                                    log::info!(target: &target, "\x1b[31merror\x1b[0m {url}: {reason}");
-                                    break;
-                                }
-                                Err(err) => {
-                                    log::error!(target: &target, "\x1b[32msubmit error\x1b[0m {url}: \x1b[35m{text:?}\x1b[0m {err:?}");
-                                    tokio::time::sleep(Duration::from_millis(1250)).await;
-                                }
+                        match self.submit(work, &text).await {
+                            Ok(result) if result.is_empty() => {
+                                log::info!(target: &target, "\x1b[36mfinished\x1b[0m {url} ...");
+                            }
+                            Ok(reason) => {
+                                log::info!(target: &target, "\x1b[31merror\x1b[0m {url}: {reason}");
+                            }
+                            Err(err) => {
+                                log::error!(target: &target, "\x1b[32msubmit error\x1b[0m {url}: {err:?}");
                             }
                         }
-                        Duration::from_millis(random_range(2400..3000))
                     }
                     Ok(text) => {
+                        // challenge pages served with 200
                         log::warn!(target: &target, "\x1b[31mwrong\x1b[0m {url}: {text}");
-                        const { Duration::from_secs(4) }
+                        self.limiter.backoff(&key, None).await;
                     }
                     Err(e) => {
                         log::error!(target: &target, "fetch error: {e:?}");
-                        const { Duration::from_secs(4) }
+                        self.limiter.backoff(&key, None).await;
                     }
-                };
-                tokio::time::sleep(sleep).await;
+                }
             }
         }
     }
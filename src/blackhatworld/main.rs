@@ -1,6 +1,8 @@
 #![feature(iter_next_chunk, try_blocks)]
 
 mod scrape;
+#[cfg(test)]
+mod tests;
 
 #[rustfmt::skip]
 const CONFIGS: [(&str, i64); 65] = [
@@ -75,10 +77,54 @@ const CONFIGS: [(&str, i64); 65] = [
     ("service-reviews-beta-testers-help-wanted", 165),
 ];
 
+const HOST: &str = "www.blackhatworld.com";
+
+#[derive(clap::Parser)]
+struct Args {
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+    #[command(flatten)]
+    sink: uscr::sink::SinkArgs,
+    #[command(flatten)]
+    rate: uscr::scrape::limit::RateArgs,
+    #[command(flatten)]
+    archive: uscr::scrape::archive::ArchiveArgs,
+    #[arg(long, value_name = "dir", help = "Parse the forum pages archived in this directory instead of browsing")]
+    replay: Option<std::path::PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    use clap::Parser;
+    use uscr::scrape::limit::{Limiter, Policy};
+
     pretty_env_logger::init_timed();
-    uscr::db::init_db().await;
+
+    let args = Args::parse();
+    let sink = args.sink.open(&args.db).await?;
+
+    if let Some(ref dir) = args.replay {
+        use uscr::scrape::{
+            archive::{Archived, Kind, Replay},
+            pipeline::Pipeline,
+        };
+
+        let reg_section = regex::Regex::new(r"/forums/[^/]*\.(\d+)/page-").unwrap();
+        let source = Replay::new(dir, uscr::scrape::archive::entries(dir)?, |page: Archived| {
+            if page.entry.kind != Kind::Resource {
+                return None;
+            }
+            let section = reg_section.captures(&page.entry.url)?.get(1)?.as_str().parse().ok()?;
+            Some(Ok((section, page.date(), page.body)))
+        });
+        Pipeline::new("replay", &sink).run(source, &scrape::ListParser::new()).await;
+        return Ok(());
+    }
+
+    let limiter = Limiter::new(
+        &[(HOST, Policy::delay(const { core::time::Duration::from_secs(2) }))],
+        args.rate.rates,
+    );
 
     let browser = uscr::scrape::puppeteer(false, None)?;
 
@@ -87,20 +133,17 @@ async fn main() -> anyhow::Result<()> {
     let mut ctx = scrape::Context {
         tab,
         cfg: Default::default(),
-        reg_id: regex::Regex::new(r"js-threadListItem-(\d+)").unwrap(),
-        sel_struct_item: scraper::Selector::parse(".structItem").unwrap(),
-        sel_title: scraper::Selector::parse(".structItem-title>a").unwrap(),
-        sel_udt: scraper::Selector::parse("time.u-dt").unwrap(),
-        sel_dd: scraper::Selector::parse("dd").unwrap(),
+        parser: scrape::ListParser::new(),
+        sink,
+        archive: args.archive.open("blackhatworld")?,
     };
 
     for config in CONFIGS {
         ctx.cfg = config;
         let mut page = 1;
         loop {
-            let r = scrape::work(page, &ctx).await;
-            tokio::time::sleep(const { core::time::Duration::from_millis(2000) }).await;
-            if r.is_break() {
+            limiter.wait(HOST).await;
+            if scrape::work(page, &ctx).await.is_break() {
                 break;
             }
             page += 1;
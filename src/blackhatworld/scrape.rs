@@ -7,81 +7,102 @@ use std::{
 use headless_chrome::Tab;
 use regex::Regex;
 use scraper::{Html, Selector};
+use serde::Serialize;
+use tokio_postgres::types::Type;
 use uscr::{
-    db::{BB8Error, ToSqlIter, get_connection},
-    scrape::puppeteer,
-    util::xmax_to_success,
+    db::bulk::Bulk,
+    scrape::{archive::Archive, pipeline::Parser, puppeteer},
+    sink::{AnySink, Record, Sink},
 };
 
 pub struct Context {
     pub tab: Arc<Tab>,
     pub cfg: (&'static str, i64),
-    pub reg_id: Regex,
-    pub sel_struct_item: Selector,
-    pub sel_title: Selector,
-    pub sel_udt: Selector,
-    pub sel_dd: Selector,
+    pub parser: ListParser,
+    pub sink: AnySink,
+    pub archive: Option<Archive>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Post {
     pub id: i64,
     pub author: String,
     pub title: String,
+    #[serde(rename = "create_time", serialize_with = "uscr::sink::timestamp")]
     pub time: SystemTime,
     pub replies: i64,
     pub views: i64,
+    #[serde(rename = "last_reply", serialize_with = "uscr::sink::timestamp")]
     pub lastReply: SystemTime,
+    pub section: i64,
+    /// When the forum page was captured.
+    #[serde(rename = "time", serialize_with = "uscr::sink::timestamp")]
+    pub seen: SystemTime,
 }
 
-#[allow(clippy::too_many_lines, clippy::used_underscore_items)]
-pub async fn work(page: i32, ctx: &Context) -> ControlFlow<(), ()> {
-    #[inline]
-    fn _pa(x: String) -> Option<i64> {
-        x.replace('K', "000").replace('M', "000000").parse().ok()
-    }
-
-    tracing::info!(target: "worker", "[Forum \x1b[33m{}\x1b[0m] [Page \x1b[32m#{page}\x1b[0m] start", ctx.cfg.0);
-
-    let url = format!(
-        "https://www.blackhatworld.com/forums/{}.{}/page-{page}/?order=post_date&direction=desc",
-        ctx.cfg.0, ctx.cfg.1,
-    );
-
-    if let Err(e) = puppeteer::navigate_to(&ctx.tab, url.into()).await {
-        tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
-        return ControlFlow::Continue(());
+impl Record for Post {
+    const TABLE: &'static str = "blackhatworld.posts";
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("time", Type::TIMESTAMP, |x| x.seen)
+            .column("author", Type::TEXT, |x| &*x.author)
+            .column("title", Type::TEXT, |x| &*x.title)
+            .column("create_time", Type::TIMESTAMP, |x| x.time)
+            .column("replies", Type::INT8, |x| x.replies)
+            .column("views", Type::INT8, |x| x.views)
+            .column("last_reply", Type::TIMESTAMP, |x| x.lastReply)
+            .column("section", Type::INT8, |x| x.section)
+            // replayed pages may be older than what is stored
+            .on_conflict_set(&["id"], &[
+                ("author", "case when excluded.time >= blackhatworld.posts.time then excluded.author else blackhatworld.posts.author end"),
+                ("title", "case when excluded.time >= blackhatworld.posts.time then excluded.title else blackhatworld.posts.title end"),
+                ("replies", "case when excluded.time >= blackhatworld.posts.time then excluded.replies else blackhatworld.posts.replies end"),
+                ("views", "case when excluded.time >= blackhatworld.posts.time then excluded.views else blackhatworld.posts.views end"),
+                ("last_reply", "case when excluded.time >= blackhatworld.posts.time then excluded.last_reply else blackhatworld.posts.last_reply end"),
+                ("time", "greatest(blackhatworld.posts.time, excluded.time)"),
+            ])
     }
+}
 
-    if let Err(e) = puppeteer::wait_for_async(&ctx.tab, ".js-threadList".into()).await {
-        tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
-        return ControlFlow::Continue(());
-    }
+/// Parses the thread list of a forum page, tagged with the forum id.
+pub struct ListParser {
+    pub reg_id: Regex,
+    pub sel_struct_item: Selector,
+    pub sel_title: Selector,
+    pub sel_udt: Selector,
+    pub sel_dd: Selector,
+}
 
-    let list = match puppeteer::find_async(&ctx.tab, ".structItemContainer".into()).await {
-        Ok(t) => t,
-        Err(e) => {
-            tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
-            return ControlFlow::Continue(());
+impl ListParser {
+    pub fn new() -> Self {
+        Self {
+            reg_id: Regex::new(r"js-threadListItem-(\d+)").unwrap(),
+            sel_struct_item: Selector::parse(".structItem").unwrap(),
+            sel_title: Selector::parse(".structItem-title>a").unwrap(),
+            sel_udt: Selector::parse("time.u-dt").unwrap(),
+            sel_dd: Selector::parse("dd").unwrap(),
         }
-    };
+    }
 
-    let html = match puppeteer::outer_html(&list).await {
-        Ok(t) => t,
-        Err(e) => {
-            tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
-            return ControlFlow::Continue(());
+    /// The threads of the list `html` of the forum `section`, captured at
+    /// `seen`.
+    #[allow(clippy::used_underscore_items)]
+    pub fn posts(&self, html: &str, section: i64, seen: SystemTime) -> Vec<Post> {
+        #[inline]
+        fn _pa(x: String) -> Option<i64> {
+            x.replace('K', "000").replace('M', "000000").parse().ok()
         }
-    };
 
-    let res = {
-        let fragment = Html::parse_fragment(&html);
+        let fragment = Html::parse_fragment(html);
         fragment
-            .select(&ctx.sel_struct_item)
+            .select(&self.sel_struct_item)
             .filter_map(|entry| {
                 let c = entry.child_elements().next_chunk::<4>().ok()?;
 
-                let id = ctx
+                let id = self
                     .reg_id
                     .captures(entry.attr("class")?)?
                     .get(1)?
@@ -90,25 +111,25 @@ pub async fn work(page: i32, ctx: &Context) -> ControlFlow<(), ()> {
                     .ok()?;
                 let author = entry.attr("data-author")?.to_owned();
                 let title = c[1]
-                    .select(&ctx.sel_title)
+                    .select(&self.sel_title)
                     .next()?
                     .text()
                     .map(str::trim)
                     .collect();
                 let time = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(
-                    c[1].select(&ctx.sel_udt)
+                    c[1].select(&self.sel_udt)
                         .next()?
                         .attr("data-time")?
                         .parse()
                         .ok()?,
                 ))?;
 
-                let mut dd = c[2].select(&ctx.sel_dd);
+                let mut dd = c[2].select(&self.sel_dd);
                 let replies = _pa(dd.next()?.text().map(str::trim).collect())?;
                 let views = _pa(dd.next()?.text().map(str::trim).collect())?;
 
                 let lastReply = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(
-                    c[3].select(&ctx.sel_udt)
+                    c[3].select(&self.sel_udt)
                         .next()?
                         .attr("data-time")?
                         .parse()
@@ -123,40 +144,74 @@ pub async fn work(page: i32, ctx: &Context) -> ControlFlow<(), ()> {
                     replies,
                     views,
                     lastReply,
+                    section,
+                    seen,
                 })
             })
-            .collect::<Vec<_>>()
+            .collect()
+    }
+}
+
+impl Parser<(i64, SystemTime, String)> for ListParser {
+    type Output = Vec<Post>;
+
+    fn parse(&self, (section, seen, html): &(i64, SystemTime, String)) -> anyhow::Result<Vec<Post>> {
+        Ok(self.posts(html, *section, *seen))
+    }
+}
+
+pub async fn work(page: i32, ctx: &Context) -> ControlFlow<(), ()> {
+    tracing::info!(target: "worker", "[Forum \x1b[33m{}\x1b[0m] [Page \x1b[32m#{page}\x1b[0m] start", ctx.cfg.0);
+
+    let url = format!(
+        "https://www.blackhatworld.com/forums/{}.{}/page-{page}/?order=post_date&direction=desc",
+        ctx.cfg.0, ctx.cfg.1,
+    );
+
+    if let Err(e) = puppeteer::navigate_to(&ctx.tab, url.clone().into()).await {
+        tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
+        return ControlFlow::Continue(());
+    }
+
+    if let Err(e) = puppeteer::wait_for_async(&ctx.tab, ".js-threadList".into()).await {
+        tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
+        return ControlFlow::Continue(());
+    }
+
+    let list = match puppeteer::find_async(&ctx.tab, ".structItemContainer".into()).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
+            return ControlFlow::Continue(());
+        }
+    };
+
+    let html = match puppeteer::outer_html(&list).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::warn!(target: "worker", "[Page #{page}] err: {e:?}");
+            return ControlFlow::Continue(());
+        }
     };
 
+    let seen = SystemTime::now();
+    if let Some(ref archive) = ctx.archive && let Err(e) = archive.resource(&url, &html, seen) {
+        tracing::error!(target: "archive", "\x1b[31m{url}\x1b[0m {e}");
+    }
+
+    let res = ctx.parser.posts(&html, ctx.cfg.1, seen);
+
     if !res.is_empty() {
-        let res: Result<(), BB8Error> = try {
-            const SQL: &str = "with tmp_insert(i, a, t, c, r, v, l) as (select * from unnest($2::bigint[], $3::text[], $4::text[], $5::timestamp[], $6::bigint[], $7::bigint[], $8::timestamp[])) insert into blackhatworld.posts (id, time, author, title, create_time, replies, views, last_reply, section) select i, now() at time zone 'UTC', a, t, c, r, v, l, $1 from tmp_insert on conflict (id) do update set time = excluded.time, author = excluded.author, title = excluded.title, replies = excluded.replies, views = excluded.views, last_reply = excluded.last_reply returning xmax";
-
-            let mut conn = get_connection().await?;
-            let stmt = conn.prepare_static(SQL.into()).await?;
-            let rows = conn
-                .query(&stmt, &[
-                    &ctx.cfg.1,
-                    &ToSqlIter(res.iter().map(|x| x.id)),
-                    &ToSqlIter(res.iter().map(|x| &*x.author)),
-                    &ToSqlIter(res.iter().map(|x| &*x.title)),
-                    &ToSqlIter(res.iter().map(|x| x.time)),
-                    &ToSqlIter(res.iter().map(|x| x.replies)),
-                    &ToSqlIter(res.iter().map(|x| x.views)),
-                    &ToSqlIter(res.iter().map(|x| x.lastReply)),
-                ])
-                .await?;
-
-            let n_rows = xmax_to_success(rows.iter());
-
-            tracing::info!(target: "db", "\x1b[36m[Page #{page}] update {n_rows}/{} items\x1b[0m", res.len());
-
-            if n_rows == 0 {
-                return ControlFlow::Break(());
+        match ctx.sink.write(&res).await {
+            Ok(upserted) => {
+                let n_rows = upserted.inserted;
+                tracing::info!(target: "db", "\x1b[36m[Page #{page}] update {n_rows}/{} items\x1b[0m", res.len());
+
+                if n_rows == 0 {
+                    return ControlFlow::Break(());
+                }
             }
-        };
-        if let Err(e) = res {
-            tracing::error!(target: "db", "\x1b[31m[Page #{page}] db err: {e}\x1b[0m");
+            Err(e) => tracing::error!(target: "db", "\x1b[31m[Page #{page}] db err: {e}\x1b[0m"),
         }
     }
 
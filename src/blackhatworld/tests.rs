@@ -0,0 +1,63 @@
+//! These run against the database configured as for `blackhatworld` itself,
+//! inside a transaction that is rolled back:
+//! `cargo test --bin blackhatworld -- --ignored`.
+
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+use uscr::sink::Record;
+
+use crate::scrape::Post;
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+}
+
+fn post(replies: i64, seen: SystemTime) -> Post {
+    Post {
+        id: -42,
+        author: "uscr".into(),
+        title: format!("{replies} replies"),
+        time: at(0),
+        replies,
+        views: replies * 10,
+        lastReply: at(replies.cast_unsigned()),
+        section: 18,
+        seen,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated database"]
+async fn replay_older_page() {
+    const POST: &str = "select title, replies, views, last_reply, \"time\" from blackhatworld.posts where id = -42";
+
+    uscr::db::init_db(crate::Args::parse_from(["blackhatworld"]).db.config().unwrap()).await.unwrap();
+    let mut conn = uscr::db::get_connection().await.unwrap();
+    let txn = conn.transaction().await.unwrap();
+
+    let write = |post: Post| {
+        let txn = &txn;
+        async move {
+            let rows = [post];
+            let bulk = Post::bulk(&rows);
+            txn.query(&bulk.sql(), &bulk.params()).await.unwrap();
+            let row = txn.query_one(POST, &[]).await.unwrap();
+            (
+                row.get::<_, String>(0),
+                row.get::<_, i64>(1),
+                row.get::<_, i64>(2),
+                row.get::<_, SystemTime>(3),
+                row.get::<_, SystemTime>(4),
+            )
+        }
+    };
+
+    let newer = ("20 replies".to_owned(), 20, 200, at(20), at(1000));
+    assert_eq!(write(post(20, at(1000))).await, newer);
+    // an archived page from before keeps the thread as it is now
+    assert_eq!(write(post(5, at(500))).await, newer);
+    assert_eq!(write(post(30, at(2000))).await, ("30 replies".to_owned(), 30, 300, at(30), at(2000)));
+
+    txn.rollback().await.unwrap();
+}
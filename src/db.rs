@@ -2,12 +2,19 @@ use core::fmt::Debug;
 use std::sync::OnceLock;
 
 use bb8_postgres::{PostgresConnectionManager, bb8};
+use postgres_native_tls::MakeTlsConnector;
 use tokio_postgres::{
-    NoTls, Row,
+    Row,
     types::{FromSql, IsNull, Kind, ToSql, Type, to_sql_checked},
 };
 
-pub type ConnectionManager = PostgresConnectionManager<NoTls>;
+pub mod bulk;
+pub mod config;
+pub mod migrate;
+
+pub use config::DBArgs;
+
+pub type ConnectionManager = PostgresConnectionManager<MakeTlsConnector>;
 pub type Pool = bb8::Pool<ConnectionManager>;
 pub type PooledConnection = bb8::PooledConnection<'static, ConnectionManager>;
 pub type DBError = tokio_postgres::Error;
@@ -16,49 +23,16 @@ pub type DBResult<T> = Result<T, DBError>;
 
 static POOL: OnceLock<Pool> = OnceLock::new();
 
-mod constants {
-    use core::time::Duration;
-
-    macro_rules! env_or_default {
-        ($name:expr, $default:expr) => {
-            if let Some(s) = option_env!($name) {
-                s
-            } else {
-                $default
-            }
-        };
-    }
-
-    pub const HOST: &str = env_or_default!("DB_HOST", "/var/run/postgresql");
-    pub const USER: &str = env_or_default!("DB_USER", "postgres");
-    pub const DBNAME: &str = env_or_default!("DB_NAME", "postgres");
-    pub const PASSWORD: Option<&str> = option_env!("DB_PASSWORD");
-    pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
-}
-
-pub async fn init_db() {
-    use constants::{CONNECTION_TIMEOUT, DBNAME, HOST, PASSWORD, USER};
-
-    let mut config = tokio_postgres::Config::new();
-    config
-        .host_path(HOST)
-        .user(USER)
-        .dbname(DBNAME)
-        .connect_timeout(CONNECTION_TIMEOUT);
-    if let Some(password) = PASSWORD {
-        config.password(password);
-    }
-
-    let manager = PostgresConnectionManager::new(config, NoTls);
+pub async fn init_db(config: tokio_postgres::Config) -> anyhow::Result<()> {
+    let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+    let manager = PostgresConnectionManager::new(config, tls);
 
-    #[allow(clippy::unwrap_used)]
     let pool = Pool::builder()
-        .connection_timeout(CONNECTION_TIMEOUT)
+        .connection_timeout(config::CONNECTION_TIMEOUT)
         .build(manager)
-        .await
-        .unwrap();
+        .await?;
 
-    POOL.set(pool).unwrap();
+    POOL.set(pool).map_err(|_| anyhow::anyhow!("database already initialized"))
 }
 
 #[inline(always)]
@@ -0,0 +1,463 @@
+use core::{
+    fmt::{Debug, Write},
+    pin::pin,
+};
+
+use tokio_postgres::{
+    Client, Row,
+    binary_copy::BinaryCopyInWriter,
+    types::{ToSql, Type},
+};
+
+use super::{DBResult, ToSqlIter};
+use crate::util::xmax_to_success;
+
+type Param<'r> = Box<dyn ToSql + Send + Sync + 'r>;
+type Getter<'r, R> = Box<dyn Fn(&'r R) -> Param<'r> + Send + Sync + 'r>;
+
+enum Source<'r, R> {
+    /// One value per row, sent as an array and expanded by `unnest` (or
+    /// streamed row by row with [`Bulk::copy`]).
+    Array(Type, Param<'r>, Getter<'r, R>),
+    /// A single value shared by all rows.
+    Constant(Type, &'r (dyn ToSql + Sync)),
+    /// A SQL expression over the other columns (referenced by name).
+    Expr(&'static str),
+}
+
+enum OnConflict {
+    Error,
+    Nothing(&'static [&'static str]),
+    Update(&'static [&'static str], &'static [&'static str]),
+    Set(&'static [&'static str], &'static [(&'static str, &'static str)]),
+}
+
+/// Number of rows written by a [`Bulk`] statement, told apart through `xmax`.
+///
+/// Rows skipped by `on conflict do nothing` are in neither count.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Upserted {
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+impl Upserted {
+    #[must_use]
+    pub fn from_rows(rows: &[Row]) -> Self {
+        let inserted = xmax_to_success(rows.iter());
+        Self { inserted, updated: rows.len() - inserted }
+    }
+}
+
+/// Builds the `with tmp_insert as (select * from unnest(...)) insert ...`
+/// statement for a slice of rows, one column at a time.
+///
+/// Placeholders are numbered in the order columns are declared, so adding a
+/// column only means adding one `.column(...)` call:
+///
+/// ```ignore
+/// Bulk::new("blackhatworld.posts", &posts)
+///     .column("id", Type::INT8, |x| x.id)
+///     .column("title", Type::TEXT, |x| &*x.title)
+///     .expr("time", "now() at time zone 'UTC'")
+///     .constant("section", Type::INT8, &section)
+///     .on_conflict_update(&["id"], &["time", "title"])
+///     .execute(&mut conn)
+///     .await?;
+/// ```
+pub struct Bulk<'r, R> {
+    table: &'static str,
+    rows: &'r [R],
+    columns: Vec<(&'static str, Source<'r, R>)>,
+    on_conflict: OnConflict,
+}
+
+impl<'r, R> Bulk<'r, R>
+where
+    R: Debug + Sync,
+{
+    pub const fn new(table: &'static str, rows: &'r [R]) -> Self {
+        Self {
+            table,
+            rows,
+            columns: Vec::new(),
+            on_conflict: OnConflict::Error,
+        }
+    }
+
+    /// Adds a column whose value is taken from every row by `f`.
+    ///
+    /// `ty` must be the (non-array) type of the target column.
+    #[must_use]
+    pub fn column<T, F>(mut self, name: &'static str, ty: Type, f: F) -> Self
+    where
+        T: ToSql + Send + Sync + 'r,
+        F: Fn(&'r R) -> T + Clone + Send + Sync + 'r,
+    {
+        let param = Box::new(ToSqlIter(self.rows.iter().map(f.clone())));
+        let getter = Box::new(move |row| Box::new(f(row)) as Param<'r>);
+        self.columns.push((name, Source::Array(ty, param, getter)));
+        self
+    }
+
+    /// Adds a column holding the same value for all rows.
+    #[must_use]
+    pub fn constant(mut self, name: &'static str, ty: Type, value: &'r (dyn ToSql + Sync)) -> Self {
+        self.columns.push((name, Source::Constant(ty, value)));
+        self
+    }
+
+    /// Adds a column computed by a SQL expression, which may refer to the
+    /// other columns by name.
+    #[must_use]
+    pub fn expr(mut self, name: &'static str, sql: &'static str) -> Self {
+        self.columns.push((name, Source::Expr(sql)));
+        self
+    }
+
+    #[must_use]
+    pub const fn on_conflict_nothing(mut self, target: &'static [&'static str]) -> Self {
+        self.on_conflict = OnConflict::Nothing(target);
+        self
+    }
+
+    /// On conflict with `target`, overwrites the `update` columns with the new values.
+    #[must_use]
+    pub const fn on_conflict_update(
+        mut self,
+        target: &'static [&'static str],
+        update: &'static [&'static str],
+    ) -> Self {
+        self.on_conflict = OnConflict::Update(target, update);
+        self
+    }
+
+    /// On conflict with `target`, sets each column to its SQL expression, which
+    /// may refer to the stored row by the table name (e.g. `accs.category.id`)
+    /// and to the new one as `excluded`.
+    #[must_use]
+    pub const fn on_conflict_set(
+        mut self,
+        target: &'static [&'static str],
+        set: &'static [(&'static str, &'static str)],
+    ) -> Self {
+        self.on_conflict = OnConflict::Set(target, set);
+        self
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The statement text; it only depends on the declared columns, never on the rows.
+    #[must_use]
+    pub fn sql(&self) -> String {
+        let mut sql = self.render(None);
+        sql.push_str(" returning xmax");
+        sql
+    }
+
+    /// The staging table of [`Self::copy`]: the statements creating it (one
+    /// column per [`Self::column`]) and copying the rows into it.
+    fn stage_sql(&self) -> (String, String) {
+        let mut defs = Vec::new();
+        let mut aliases = Vec::new();
+        for (name, source) in &self.columns {
+            if let Source::Array(ty, ..) = source {
+                defs.push(format!("\"{name}\" {}", ty.name()));
+                aliases.push(format!("\"{name}\""));
+            }
+        }
+        (
+            format!("create temp table {STAGE} ({}) on commit drop", defs.join(", ")),
+            format!("copy {STAGE} ({}) from stdin (format binary)", aliases.join(", ")),
+        )
+    }
+
+    /// The statement merging the staging table into the target, returning the
+    /// number of inserted rows and of all rows written; its placeholders are
+    /// the constants.
+    fn merge_sql(&self) -> String {
+        format!(
+            "with ins as ({} returning xmax) select count(*) filter (where xmax::text = '0'), count(*) from ins",
+            self.render(Some(STAGE)),
+        )
+    }
+
+    /// Renders `with tmp_insert as (...) insert ... select ... [on conflict ...]`,
+    /// reading the per-row columns either from `unnest` over array parameters or,
+    /// given a staging table, from that table.
+    fn render(&self, stage: Option<&str>) -> String {
+        let mut consts = String::new();
+        let mut arrays = Vec::new();
+        let mut aliases = Vec::new();
+        let mut targets = Vec::with_capacity(self.columns.len());
+        let mut values = Vec::with_capacity(self.columns.len());
+
+        let mut n = 0;
+        for (name, source) in &self.columns {
+            targets.push(format!("\"{name}\""));
+            match source {
+                Source::Array(ty, ..) => {
+                    if stage.is_none() {
+                        n += 1;
+                        arrays.push(format!("${n}::{}[]", ty.name()));
+                    }
+                    aliases.push(format!("\"{name}\""));
+                    values.push(format!("\"{name}\""));
+                }
+                Source::Constant(ty, _) => {
+                    n += 1;
+                    let _ = write!(consts, ", ${n}::{} as \"{name}\"", ty.name());
+                    values.push(format!("\"{name}\""));
+                }
+                Source::Expr(sql) => values.push(format!("{sql} as \"{name}\"")),
+            }
+        }
+
+        let mut sql = if let Some(stage) = stage {
+            // Duplicated keys would make `on conflict do update` fail, keep the last copied row.
+            let (distinct, order) = match self.on_conflict {
+                OnConflict::Error => (String::new(), String::new()),
+                OnConflict::Nothing(target) | OnConflict::Update(target, _) | OnConflict::Set(target, _) => {
+                    let target = quote_list(target);
+                    (format!("distinct on ({target}) "), format!(" order by {target}, \"ctid\" desc"))
+                }
+            };
+            format!(
+                "with tmp_insert as (select t.*, t.ctid as \"ctid\"{consts} from {stage} as t) insert into {} ({}) select {distinct}{} from tmp_insert{order}",
+                self.table,
+                targets.join(", "),
+                values.join(", "),
+            )
+        } else {
+            format!(
+                "with tmp_insert as (select t.*{consts} from unnest({}) as t({})) insert into {} ({}) select {} from tmp_insert",
+                arrays.join(", "),
+                aliases.join(", "),
+                self.table,
+                targets.join(", "),
+                values.join(", "),
+            )
+        };
+
+        match self.on_conflict {
+            OnConflict::Error => (),
+            OnConflict::Nothing(target) => {
+                let _ = write!(sql, " on conflict ({}) do nothing", quote_list(target));
+            }
+            OnConflict::Update(target, update) => {
+                let set = update.iter().map(|name| format!("\"{name}\" = excluded.\"{name}\"")).collect::<Vec<_>>();
+                let _ = write!(sql, " on conflict ({}) do update set {}", quote_list(target), set.join(", "));
+            }
+            OnConflict::Set(target, set) => {
+                let set = set.iter().map(|(name, expr)| format!("\"{name}\" = {expr}")).collect::<Vec<_>>();
+                let _ = write!(sql, " on conflict ({}) do update set {}", quote_list(target), set.join(", "));
+            }
+        }
+
+        sql
+    }
+
+    /// The parameters matching the placeholders of [`Self::sql`].
+    #[must_use]
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.columns
+            .iter()
+            .filter_map(|(_, source)| match source {
+                Source::Array(_, param, _) => Some(&**param as &(dyn ToSql + Sync)),
+                Source::Constant(_, value) => Some(*value),
+                Source::Expr(_) => None,
+            })
+            .collect()
+    }
+
+    /// Prepares (once per connection) and runs the statement.
+    pub async fn execute(&self, conn: &mut Client) -> DBResult<Upserted> {
+        if self.rows.is_empty() {
+            return Ok(Upserted::default());
+        }
+
+        let stmt = conn.prepare_static(self.sql().into()).await?;
+        let rows = conn.query(&stmt, &self.params()).await?;
+        Ok(Upserted::from_rows(&rows))
+    }
+
+    /// Same effect as [`Self::execute`], for large batches: the rows are streamed
+    /// with `COPY ... FROM STDIN (FORMAT binary)` into a temporary staging table,
+    /// then merged into the target by a single statement.
+    ///
+    /// Unlike [`Self::execute`], rows sharing a conflict key are allowed, the last
+    /// one wins.
+    pub async fn copy(&self, conn: &mut Client) -> DBResult<Upserted> {
+        if self.rows.is_empty() {
+            return Ok(Upserted::default());
+        }
+
+        let mut types = Vec::new();
+        let mut getters = Vec::new();
+        let mut consts = Vec::new();
+        for (_, source) in &self.columns {
+            match source {
+                Source::Array(ty, _, getter) => {
+                    types.push(ty.clone());
+                    getters.push(getter);
+                }
+                Source::Constant(_, value) => consts.push(*value),
+                Source::Expr(_) => (),
+            }
+        }
+
+        let (create, copy_in) = self.stage_sql();
+        let txn = conn.transaction().await?;
+        txn.batch_execute(&create).await?;
+
+        let sink = txn.copy_in(&copy_in).await?;
+        let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+        for row in self.rows {
+            let values = getters.iter().map(|getter| getter(row)).collect::<Vec<_>>();
+            writer.as_mut().write_raw(values).await?;
+        }
+        writer.finish().await?;
+
+        let sql = self.merge_sql();
+        let row = txn.query_one(&sql, &consts).await?;
+        txn.commit().await?;
+
+        let inserted = row.try_get::<_, i64>(0)?.cast_unsigned() as usize;
+        let total = row.try_get::<_, i64>(1)?.cast_unsigned() as usize;
+        Ok(Upserted { inserted, updated: total - inserted })
+    }
+}
+
+const STAGE: &str = "uscr_stage";
+
+fn quote_list(names: &[&str]) -> String {
+    names.iter().map(|name| format!("\"{name}\"")).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Row {
+        id: i64,
+        title: String,
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![Row { id: 1, title: "a".into() }, Row { id: 2, title: "b".into() }]
+    }
+
+    fn bulk(rows: &[Row]) -> Bulk<'_, Row> {
+        Bulk::new("s.t", rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("title", Type::TEXT, |x| &*x.title)
+    }
+
+    const UNNEST: &str = "with tmp_insert as (select t.* from unnest($1::int8[], $2::text[]) as t(\"id\", \"title\")) \
+        insert into s.t (\"id\", \"title\") select \"id\", \"title\" from tmp_insert";
+
+    #[test]
+    fn no_conflict() {
+        let rows = rows();
+        let bulk = bulk(&rows);
+        assert_eq!(bulk.sql(), format!("{UNNEST} returning xmax"));
+        assert_eq!(bulk.params().len(), 2);
+        assert_eq!(bulk.len(), 2);
+    }
+
+    #[test]
+    fn on_conflict_nothing() {
+        let rows = rows();
+        let bulk = bulk(&rows).on_conflict_nothing(&["id"]);
+        assert_eq!(bulk.sql(), format!("{UNNEST} on conflict (\"id\") do nothing returning xmax"));
+    }
+
+    #[test]
+    fn on_conflict_update() {
+        let rows = rows();
+        let bulk = bulk(&rows).on_conflict_update(&["id"], &["title"]);
+        assert_eq!(
+            bulk.sql(),
+            format!("{UNNEST} on conflict (\"id\") do update set \"title\" = excluded.\"title\" returning xmax"),
+        );
+    }
+
+    #[test]
+    fn on_conflict_set() {
+        let rows = rows();
+        let bulk = bulk(&rows).on_conflict_set(&["id"], &[("title", "s.t.title || excluded.title")]);
+        assert_eq!(
+            bulk.sql(),
+            format!("{UNNEST} on conflict (\"id\") do update set \"title\" = s.t.title || excluded.title returning xmax"),
+        );
+    }
+
+    #[test]
+    fn constant_and_expr() {
+        let rows = rows();
+        let section = 7_i64;
+        let bulk = Bulk::new("s.t", &rows)
+            .column("id", Type::INT8, |x| x.id)
+            .constant("section", Type::INT8, &section)
+            .expr("time", "now() at time zone 'UTC'")
+            .column("title", Type::TEXT, |x| &*x.title);
+        // placeholders follow the declaration order, expressions take none
+        assert_eq!(
+            bulk.sql(),
+            "with tmp_insert as (select t.*, $2::int8 as \"section\" from unnest($1::int8[], $3::text[]) as t(\"id\", \"title\")) \
+             insert into s.t (\"id\", \"section\", \"time\", \"title\") \
+             select \"id\", \"section\", now() at time zone 'UTC' as \"time\", \"title\" from tmp_insert returning xmax",
+        );
+        assert_eq!(bulk.params().len(), 3);
+    }
+
+    #[test]
+    fn copy_stage() {
+        let rows = rows();
+        let section = 7_i64;
+        let bulk = bulk(&rows).constant("section", Type::INT8, &section).expr("time", "now()");
+        let (create, copy_in) = bulk.stage_sql();
+        // only the per-row columns are staged
+        assert_eq!(create, "create temp table uscr_stage (\"id\" int8, \"title\" text) on commit drop");
+        assert_eq!(copy_in, "copy uscr_stage (\"id\", \"title\") from stdin (format binary)");
+    }
+
+    #[test]
+    fn copy_merge() {
+        let rows = rows();
+        let section = 7_i64;
+        let bulk = bulk(&rows).constant("section", Type::INT8, &section);
+        assert_eq!(
+            bulk.merge_sql(),
+            "with ins as (with tmp_insert as (select t.*, t.ctid as \"ctid\", $1::int8 as \"section\" from uscr_stage as t) \
+             insert into s.t (\"id\", \"title\", \"section\") select \"id\", \"title\", \"section\" from tmp_insert returning xmax) \
+             select count(*) filter (where xmax::text = '0'), count(*) from ins",
+        );
+
+        // the last copied row of a key wins
+        let bulk = bulk.on_conflict_update(&["id"], &["title"]);
+        assert_eq!(
+            bulk.merge_sql(),
+            "with ins as (with tmp_insert as (select t.*, t.ctid as \"ctid\", $1::int8 as \"section\" from uscr_stage as t) \
+             insert into s.t (\"id\", \"title\", \"section\") select distinct on (\"id\") \"id\", \"title\", \"section\" from tmp_insert \
+             order by \"id\", \"ctid\" desc on conflict (\"id\") do update set \"title\" = excluded.\"title\" returning xmax) \
+             select count(*) filter (where xmax::text = '0'), count(*) from ins",
+        );
+    }
+
+    #[test]
+    fn same_sql_for_any_rows() {
+        let rows = rows();
+        assert_eq!(bulk(&rows).sql(), bulk(&rows[..1]).sql());
+        assert!(bulk(&[]).is_empty());
+    }
+}
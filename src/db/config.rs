@@ -0,0 +1,254 @@
+use core::time::Duration;
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use serde::Deserialize;
+use tokio_postgres::{Config, config::SslMode};
+
+const DEFAULT_HOST: &str = "/var/run/postgresql";
+const DEFAULT_USER: &str = "postgres";
+const DEFAULT_DBNAME: &str = "postgres";
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Database options shared by every binary.
+///
+/// Settings are resolved at runtime, later sources overriding earlier ones:
+/// built-in defaults, the JSON file given by `--db-config`, the `DB_*`
+/// environment variables, and finally `--database-url` (only the fields it
+/// actually specifies).
+#[derive(Debug, clap::Args)]
+pub struct DBArgs {
+    #[arg(
+        long,
+        global = true,
+        env = "DATABASE_URL",
+        hide_env_values = true,
+        value_name = "url",
+        help = "Connection string, as a postgresql:// URL or key=value pairs"
+    )]
+    pub database_url: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        env = "DB_CONFIG",
+        value_name = "file",
+        help = "JSON file with database settings"
+    )]
+    pub db_config: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    #[default]
+    Disable,
+    Prefer,
+    Require,
+}
+
+impl TlsMode {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "disable" => Self::Disable,
+            "prefer" => Self::Prefer,
+            "require" => Self::Require,
+            _ => anyhow::bail!("unknown ssl mode {s:?}, expected disable, prefer or require"),
+        })
+    }
+}
+
+impl From<TlsMode> for SslMode {
+    fn from(mode: TlsMode) -> Self {
+        match mode {
+            TlsMode::Disable => Self::Disable,
+            TlsMode::Prefer => Self::Prefer,
+            TlsMode::Require => Self::Require,
+        }
+    }
+}
+
+/// Contents of the `--db-config` file, every field optional.
+///
+/// A `host` starting with `/` is a Unix socket directory, anything else is a TCP host.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub dbname: Option<String>,
+    pub password: Option<String>,
+    pub sslmode: Option<TlsMode>,
+}
+
+impl Settings {
+    fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(Into::into)
+    }
+
+    /// Overrides the fields whose `DB_*` variable `env` gives (empty means
+    /// unset).
+    fn merge_env(&mut self, env: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        let var = |name: &str| env(name).filter(|s| !s.is_empty());
+
+        if let Some(host) = var("DB_HOST") {
+            self.host = Some(host);
+        }
+        if let Some(port) = var("DB_PORT") {
+            self.port = Some(port.parse()?);
+        }
+        if let Some(user) = var("DB_USER") {
+            self.user = Some(user);
+        }
+        if let Some(dbname) = var("DB_NAME") {
+            self.dbname = Some(dbname);
+        }
+        if let Some(password) = var("DB_PASSWORD") {
+            self.password = Some(password);
+        }
+        if let Some(sslmode) = var("DB_SSLMODE") {
+            self.sslmode = Some(TlsMode::parse(&sslmode)?);
+        }
+        Ok(())
+    }
+
+    fn apply(self, config: &mut Config, keep_ssl_mode: bool) {
+        if config.get_hosts().is_empty() {
+            let host = self.host.as_deref().unwrap_or(DEFAULT_HOST);
+            if host.starts_with('/') {
+                config.host_path(host);
+            } else {
+                config.host(host);
+            }
+        }
+        if config.get_ports().is_empty() && let Some(port) = self.port {
+            config.port(port);
+        }
+        if config.get_user().is_none() {
+            config.user(self.user.as_deref().unwrap_or(DEFAULT_USER));
+        }
+        if config.get_dbname().is_none() {
+            config.dbname(self.dbname.as_deref().unwrap_or(DEFAULT_DBNAME));
+        }
+        if config.get_password().is_none() && let Some(password) = self.password {
+            config.password(password);
+        }
+        if !keep_ssl_mode {
+            config.ssl_mode(self.sslmode.unwrap_or_default().into());
+        }
+        if config.get_connect_timeout().is_none() {
+            config.connect_timeout(CONNECTION_TIMEOUT);
+        }
+    }
+}
+
+impl DBArgs {
+    pub fn config(&self) -> anyhow::Result<Config> {
+        self.resolve(|name| std::env::var(name).ok())
+    }
+
+    /// [`Self::config`], with the environment variables given by `env`.
+    fn resolve(&self, env: impl Fn(&str) -> Option<String>) -> anyhow::Result<Config> {
+        let mut settings = match self.db_config {
+            Some(ref path) => Settings::from_file(path)
+                .map_err(|e| anyhow::anyhow!("reading {}: {e}", path.display()))?,
+            None => Settings::default(),
+        };
+        settings.merge_env(env)?;
+
+        let (mut config, keep_ssl_mode) = match self.database_url {
+            // tokio-postgres defaults to `prefer`, which would silently turn TLS on,
+            // and cannot tell it from an explicit `sslmode=prefer`: the URL only
+            // wins for the other modes.
+            Some(ref url) => {
+                let config = url.parse::<Config>()?;
+                let keep = config.get_ssl_mode() != SslMode::Prefer;
+                (config, keep)
+            }
+            None => (Config::new(), false),
+        };
+        settings.apply(&mut config, keep_ssl_mode);
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio_postgres::config::Host;
+
+    use super::*;
+
+    fn args(database_url: Option<&str>, db_config: Option<&str>) -> (DBArgs, Option<PathBuf>) {
+        static N: AtomicUsize = AtomicUsize::new(0);
+
+        let path = db_config.map(|json| {
+            let n = N.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("uscr-db-config-{}-{n}.json", std::process::id()));
+            std::fs::write(&path, json).unwrap();
+            path
+        });
+        (DBArgs { database_url: database_url.map(Into::into), db_config: path.clone() }, path)
+    }
+
+    fn resolve(database_url: Option<&str>, db_config: Option<&str>, env: &[(&str, &str)]) -> Config {
+        let (args, path) = args(database_url, db_config);
+        let config = args.resolve(|name| env.iter().find(|(k, _)| *k == name).map(|(_, v)| (*v).into()));
+        if let Some(path) = path {
+            std::fs::remove_file(path).unwrap();
+        }
+        config.unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let config = resolve(None, None, &[]);
+        assert_eq!(config.get_hosts(), [Host::Unix(DEFAULT_HOST.into())]);
+        assert_eq!(config.get_user(), Some(DEFAULT_USER));
+        assert_eq!(config.get_dbname(), Some(DEFAULT_DBNAME));
+        assert_eq!(config.get_ssl_mode(), SslMode::Disable);
+        assert_eq!(config.get_connect_timeout(), Some(&CONNECTION_TIMEOUT));
+    }
+
+    #[test]
+    fn precedence() {
+        let file = r#"{"host": "file", "port": 1, "user": "file", "dbname": "file", "sslmode": "require"}"#;
+        let env = [("DB_HOST", "/env"), ("DB_USER", "env"), ("DB_PORT", "")];
+
+        let config = resolve(None, Some(file), &[]);
+        assert_eq!(config.get_hosts(), [Host::Tcp("file".into())]);
+        assert_eq!(config.get_ssl_mode(), SslMode::Require);
+
+        // the environment over the file, an empty variable is unset
+        let config = resolve(None, Some(file), &env);
+        assert_eq!(config.get_hosts(), [Host::Unix("/env".into())]);
+        assert_eq!(config.get_ports(), [1]);
+        assert_eq!(config.get_user(), Some("env"));
+        assert_eq!(config.get_dbname(), Some("file"));
+
+        // the URL over both, for the fields it has
+        let config = resolve(Some("postgresql://url@url:2"), Some(file), &env);
+        assert_eq!(config.get_hosts(), [Host::Tcp("url".into())]);
+        assert_eq!(config.get_ports(), [2]);
+        assert_eq!(config.get_user(), Some("url"));
+        assert_eq!(config.get_dbname(), Some("file"));
+        assert_eq!(config.get_ssl_mode(), SslMode::Require);
+
+        let config = resolve(Some("postgresql://url@url/db?sslmode=disable"), Some(file), &env);
+        assert_eq!(config.get_ssl_mode(), SslMode::Disable);
+
+        // not the default `prefer` of tokio-postgres, even with "sslmode" in the password
+        let config = resolve(Some("postgresql://u:sslmode@h/db"), None, &[]);
+        assert_eq!(config.get_ssl_mode(), SslMode::Disable);
+        let config = resolve(Some("host=h password=sslmode"), None, &[("DB_SSLMODE", "require")]);
+        assert_eq!(config.get_ssl_mode(), SslMode::Require);
+
+        // `prefer` in the URL is the default of tokio-postgres, left to the settings
+        let config = resolve(Some("host=h sslmode=prefer"), None, &[("DB_SSLMODE", "prefer")]);
+        assert_eq!(config.get_ssl_mode(), SslMode::Prefer);
+        let config = resolve(Some("postgresql://u@h/db?sslmode=prefer"), None, &[]);
+        assert_eq!(config.get_ssl_mode(), SslMode::Disable);
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::SystemTime;
+
+use tokio_postgres::Client;
+
+use super::DBResult;
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+macro_rules! migrations {
+    ($($version:literal => $name:literal),* $(,)?) => {
+        &[$(Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../../migrations/", $name, ".sql")),
+        }),*]
+    };
+}
+
+/// Every schema migration, in the order they must be applied.
+///
+/// The first migrations only use `IF NOT EXISTS`, so a database created from the
+/// DDL formerly listed in the README can adopt them without losing data.
+pub static MIGRATIONS: &[Migration] = migrations! {
+    1 => "0001_accs",
+    2 => "0002_ezkify",
+    3 => "0003_blackhatworld",
+    4 => "0004_telegram",
+    5 => "0005_hackforums",
+    6 => "0006_accs_category",
+    7 => "0007_accs_history",
+    8 => "0008_accs_detail",
+    9 => "0009_ezkify_api",
+    10 => "0010_ezkify_currency",
+    11 => "0011_ezkify_attributes",
+    12 => "0012_ezkify_resellers",
+    13 => "0013_ezkify_history",
+};
+
+const LOCK_KEY: i64 = 0x7573_6372_6d69_6772; // b"uscrmigr"
+
+async fn ensure_table(conn: &Client) -> DBResult<()> {
+    conn.batch_execute(
+        "create table if not exists public.uscr_migrations (\
+            version integer not null primary key, \
+            name text not null, \
+            applied_at timestamp without time zone not null default (now() at time zone 'UTC')\
+        )",
+    )
+    .await
+}
+
+/// Returns `(version, name, applied_at)` of the migrations already run.
+pub async fn applied(conn: &Client) -> DBResult<Vec<(i32, String, SystemTime)>> {
+    ensure_table(conn).await?;
+    let rows = conn
+        .query("select version, name, applied_at from public.uscr_migrations order by version", &[])
+        .await?;
+    rows.into_iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?)))
+        .collect()
+}
+
+/// Applies every pending migration, each one in its own transaction, and
+/// returns those that were run.
+///
+/// An advisory lock keeps two concurrent `migrate` runs from racing.
+pub async fn migrate(conn: &mut Client) -> DBResult<Vec<&'static Migration>> {
+    ensure_table(conn).await?;
+
+    let mut done = Vec::new();
+    for migration in MIGRATIONS {
+        let txn = conn.transaction().await?;
+        txn.execute("select pg_advisory_xact_lock($1)", &[&LOCK_KEY]).await?;
+
+        let exists = txn
+            .query_opt("select 1 from public.uscr_migrations where version = $1", &[&migration.version])
+            .await?
+            .is_some();
+        if exists {
+            continue;
+        }
+
+        tracing::info!(target: "migrate", "applying \x1b[33m{}\x1b[0m ...", migration.name);
+        txn.batch_execute(migration.sql).await?;
+        txn.execute(
+            "insert into public.uscr_migrations (version, name) values ($1, $2)",
+            &[&migration.version, &migration.name],
+        )
+        .await?;
+        txn.commit().await?;
+
+        done.push(migration);
+    }
+
+    Ok(done)
+}
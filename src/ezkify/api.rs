@@ -0,0 +1,128 @@
+use std::time::SystemTime;
+
+use compact_str::CompactString;
+use reqwest::{Client, IntoUrl, RequestBuilder};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use super::{
+    Category, Item, KeyedItem,
+    attrs::Attributes,
+    money::{Fx, Money},
+};
+
+/// The `action=services` request of the standard reseller API (`POST /api/v2`).
+pub fn request<U: IntoUrl>(client: &Client, url: U, api_key: &str) -> RequestBuilder {
+    client.post(url).form(&[("key", api_key), ("action", "services")])
+}
+
+/// One service in the answer to [`request`]. Numbers may come as strings.
+#[derive(Deserialize)]
+pub struct Service {
+    #[serde(rename = "service", deserialize_with = "number")]
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+    /// Per thousand, in the currency of the panel.
+    #[serde(deserialize_with = "number")]
+    pub rate: f64,
+    #[serde(deserialize_with = "number")]
+    pub min: i64,
+    #[serde(deserialize_with = "number")]
+    pub max: i64,
+    #[serde(default, deserialize_with = "flag")]
+    pub refill: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    pub cancel: Option<bool>,
+    #[serde(default, deserialize_with = "flag")]
+    pub dripfeed: Option<bool>,
+}
+
+/// The services are parsed one by one, so that a malformed one is skipped
+/// instead of failing the whole answer.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Answer {
+    Services(Vec<Value>),
+    Error { error: String },
+}
+
+fn number<'de, D: Deserializer<'de>, T: core::str::FromStr>(deserializer: D) -> Result<T, D::Error> {
+    let s = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s,
+        v => return Err(serde::de::Error::custom(format_args!("not a number: {v}"))),
+    };
+    s.trim().parse().map_err(|_| serde::de::Error::custom(format_args!("not a number: {s:?}")))
+}
+
+/// `true`, `1` or `"1"`; [`None`] if null or unrecognized.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Bool(b) => Some(b),
+        Value::Number(n) => n.as_i64().map(|n| n != 0),
+        Value::String(s) => match &*s.trim().to_lowercase() {
+            "1" | "true" | "yes" => Some(true),
+            "0" | "false" | "no" | "" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// The categories and items in the answer `body` of the panel `key`, fetched
+/// at `time`, with its rates in `currency` converted by `fx`.
+///
+/// The API only names the categories, so their ids are
+/// [`uscr::util::stable_id`] of the names, as for `"category_id": "hash"`
+/// panels.
+pub fn services(key: &CompactString, currency: &str, fx: &Fx, body: &str, time: SystemTime) -> anyhow::Result<(Vec<Category>, Vec<KeyedItem>)> {
+    let services = match serde_json::from_str(body)? {
+        Answer::Services(services) => services,
+        Answer::Error { error } => anyhow::bail!("api error: {error}"),
+    };
+
+    let mut categories = Vec::<Category>::new();
+    let mut items = Vec::with_capacity(services.len());
+    for service in services {
+        let service = match Service::deserialize(&service) {
+            Ok(service) => service,
+            Err(e) => {
+                let id = service.get("service").unwrap_or(&Value::Null);
+                tracing::error!(target: "api", "{key}: skipping service {id}: {e}");
+                continue;
+            }
+        };
+        let rate = Money { amount: service.rate, currency: currency.into() };
+        let Some(rate_per_1k) = fx.usd(&rate) else {
+            anyhow::bail!("no FX rate for {currency}");
+        };
+        let cid = uscr::util::stable_id(&service.category);
+        if !categories.iter().any(|c| c.id == cid) {
+            categories.push(Category { key: key.clone(), id: cid, desc: service.category });
+        }
+        items.push(KeyedItem {
+            key: key.clone(),
+            item: Item {
+                id: service.id,
+                time,
+                cid,
+                service: service.name,
+                rate_per_1k,
+                rate: rate.amount,
+                currency: rate.currency,
+                min_order: service.min,
+                max_order: service.max,
+                description: String::new(),
+                attrs: Attributes {
+                    refill: service.refill,
+                    cancel: service.cancel,
+                    drip_feed: service.dripfeed,
+                    ..Attributes::default()
+                },
+            },
+        });
+    }
+
+    Ok((categories, items))
+}
@@ -0,0 +1,154 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+/// What a service offers besides its price, [`None`] where the panel does
+/// not say.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Attributes {
+    pub refill: Option<bool>,
+    /// How long the refill is guaranteed.
+    pub refill_days: Option<i32>,
+    pub cancel: Option<bool>,
+    pub drip_feed: Option<bool>,
+    pub average_seconds: Option<i64>,
+}
+
+impl Attributes {
+    /// `self`, with what it lacks taken from `other`.
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            refill: self.refill.or(other.refill),
+            refill_days: self.refill_days.or(other.refill_days),
+            cancel: self.cancel.or(other.cancel),
+            drip_feed: self.drip_feed.or(other.drip_feed),
+            average_seconds: self.average_seconds.or(other.average_seconds),
+        }
+    }
+
+    /// The attributes stated in a free-text description, one per line, e.g.
+    /// `Refill: 30 days`, `No cancel`, `Drip-feed: ✅`, `Average time: 2 hours`.
+    pub fn from_description(description: &str) -> Self {
+        let rules = &*RULES;
+        let mut attrs = Self::default();
+        if let Some(found) = rules.refill.find(description) {
+            (attrs.refill, attrs.refill_days) = if found.negated {
+                (Some(false), None)
+            } else {
+                match refill(found.value) {
+                    (None, _) => refill(found.line),
+                    r => r,
+                }
+            };
+            attrs.refill = attrs.refill.or(Some(true));
+        }
+        if let Some(found) = rules.cancel.find(description) {
+            attrs.cancel = Some(!found.negated && flag(found.value).unwrap_or(true));
+        }
+        if let Some(found) = rules.drip_feed.find(description) {
+            attrs.drip_feed = Some(!found.negated && flag(found.value).unwrap_or(true));
+        }
+        if let Some(found) = rules.average.find(description) {
+            attrs.average_seconds = seconds(found.value);
+        }
+        attrs
+    }
+}
+
+/// A line of a description stating an attribute.
+struct Found<'a> {
+    line: &'a str,
+    /// What follows the attribute name, e.g. `30 days` in `Refill: 30 days`.
+    value: &'a str,
+    /// `No refill`, `Non cancel`, ...
+    negated: bool,
+}
+
+struct Rule {
+    key: Regex,
+    negated: Regex,
+}
+
+impl Rule {
+    fn new(key: &str) -> Self {
+        Self {
+            key: Regex::new(&format!(r"(?i)\b(?:{key})\b")).unwrap(),
+            negated: Regex::new(&format!(r"(?i)\b(?:no|non|not|without)[\s-]+(?:{key})\b")).unwrap(),
+        }
+    }
+
+    /// The first line stating the attribute.
+    fn find<'a>(&self, description: &'a str) -> Option<Found<'a>> {
+        description.lines().find_map(|line| {
+            let m = self.key.find(line)?;
+            let value = line[m.end()..].trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ':' | '-' | '=' | '|')).trim();
+            Some(Found { line, value, negated: self.negated.is_match(line) })
+        })
+    }
+}
+
+struct Rules {
+    refill: Rule,
+    cancel: Rule,
+    drip_feed: Rule,
+    average: Rule,
+}
+
+static RULES: LazyLock<Rules> = LazyLock::new(|| Rules {
+    refill: Rule::new(r"refill|guarantee|guaranteed"),
+    cancel: Rule::new(r"cancel|cancellation"),
+    drip_feed: Rule::new(r"drip[\s-]?feed"),
+    average: Rule::new(r"(?:avg\.?|average)(?:\s+completion)?\s+time"),
+});
+
+static DAYS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(\d+)\s*(?:days?|d)\b").unwrap());
+static DURATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(\d+(?:[.,]\d+)?)\s*(days?|d|hours?|hrs?|h|minutes?|mins?|m|seconds?|secs?|s)\b").unwrap()
+});
+
+/// `yes` / `no` and the like, as panels write them.
+pub fn flag(text: &str) -> Option<bool> {
+    match &*text.trim().trim_end_matches('.').to_lowercase() {
+        "yes" | "y" | "true" | "on" | "enabled" | "available" | "supported" | "✅" | "✔" | "✔️" | "✓" | "+" => Some(true),
+        "no" | "n" | "false" | "off" | "disabled" | "none" | "not available" | "unavailable" | "❌" | "✖" | "✗" | "×" | "-" => Some(false),
+        _ => None,
+    }
+}
+
+/// A refill cell or statement: `30 days`, `Lifetime`, `No`, ...
+pub fn refill(text: &str) -> (Option<bool>, Option<i32>) {
+    if let Some(flag) = flag(text) {
+        return (Some(flag), None);
+    }
+    if let Some(days) = DAYS.captures(text).and_then(|c| c[1].parse().ok()) {
+        return (Some(days > 0), Some(days));
+    }
+    let text = text.to_lowercase();
+    if text.contains("lifetime") || text.contains("auto") {
+        return (Some(true), None);
+    }
+    (None, None)
+}
+
+/// A duration such as `12 hours`, `1h 30m`, `2 days`, `Instant`, in seconds.
+pub fn seconds(text: &str) -> Option<i64> {
+    if text.trim().to_lowercase().starts_with("instant") {
+        return Some(0);
+    }
+    let mut total = None;
+    for c in DURATION.captures_iter(text) {
+        let Ok(n) = c[1].replace(',', ".").parse::<f64>() else {
+            continue;
+        };
+        let unit = match c[2].to_lowercase().as_bytes()[0] {
+            b'd' => 86400.0,
+            b'h' => 3600.0,
+            b'm' => 60.0,
+            _ => 1.0,
+        };
+        *total.get_or_insert(0) += (n * unit).round() as i64;
+    }
+    total
+}
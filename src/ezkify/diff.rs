@@ -0,0 +1,72 @@
+use std::time::SystemTime;
+
+use uscr::db::get_connection;
+
+/// Prints how the services of the panel `key` changed from the snapshot
+/// `from` to the snapshot `to`, counting back from the latest (0).
+pub async fn diff(key: &str, from: usize, to: usize) -> anyhow::Result<()> {
+    const SNAPSHOTS: &str = "select \"time\" from ezkify.snapshot where key = $1 order by \"time\" desc";
+    const EVENTS: &str = "select kind, id, service, rate, currency, old_service, old_rate, old_currency \
+        from ezkify.service_event \
+        where key = $1 and \"time\" > $2 and \"time\" <= $3 order by \"time\", id, kind";
+
+    anyhow::ensure!(from > to, "snapshot {from} is not older than snapshot {to}");
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(SNAPSHOTS.into()).await?;
+    let snapshots = conn.query(&stmt, &[&key]).await?;
+    let snapshot = |n: usize| -> anyhow::Result<SystemTime> {
+        let row = snapshots
+            .get(n)
+            .ok_or_else(|| anyhow::anyhow!("no snapshot {n}, {key} has {} snapshots", snapshots.len()))?;
+        Ok(row.try_get(0)?)
+    };
+    let from_time = snapshot(from)?;
+    let to_time = snapshot(to)?;
+
+    println!(
+        "{key}: changes from the snapshot at {} to the snapshot at {}",
+        httpdate::fmt_http_date(from_time),
+        httpdate::fmt_http_date(to_time),
+    );
+
+    let stmt = conn.prepare_static(EVENTS.into()).await?;
+    let rows = conn.query(&stmt, &[&key, &from_time, &to_time]).await?;
+    let (mut added, mut removed, mut renamed, mut price) = (0, 0, 0, 0);
+    for row in rows {
+        let id: i64 = row.try_get(1)?;
+        let service: &str = row.try_get(2)?;
+        let rate: f64 = row.try_get(3)?;
+        let currency: &str = row.try_get(4)?;
+        let old_service: Option<&str> = row.try_get(5)?;
+        let old_rate: Option<f64> = row.try_get(6)?;
+        let old_currency: Option<&str> = row.try_get(7)?;
+
+        match row.try_get(0)? {
+            "added" => {
+                added += 1;
+                println!("\x1b[32m+\x1b[0m {id} {rate} {currency} {service:?}");
+            }
+            "removed" => {
+                removed += 1;
+                println!("\x1b[31m-\x1b[0m {id} {rate} {currency} {service:?}");
+            }
+            "renamed" => {
+                renamed += 1;
+                println!("\x1b[36m~\x1b[0m {id} {:?} -> {service:?}", old_service.unwrap_or_default());
+            }
+            "price" => {
+                price += 1;
+                println!(
+                    "\x1b[33m$\x1b[0m {id} {} {} -> {rate} {currency} {service:?}",
+                    old_rate.unwrap_or_default(),
+                    old_currency.unwrap_or_default(),
+                );
+            }
+            kind => tracing::warn!(target: "diff", "unknown event {kind:?}"),
+        }
+    }
+
+    println!("{added} added, {removed} removed, {renamed} renamed and {price} repriced");
+    Ok(())
+}
@@ -1,187 +1,366 @@
-#![feature(iter_array_chunks, iter_next_chunk)]
-
-mod parse_item;
+mod api;
+mod attrs;
+mod diff;
+mod money;
+mod panel;
+mod report;
+mod resellers;
+mod taxonomy;
+#[cfg(test)]
+mod tests;
 
 #[derive(clap::Parser)]
 struct Args {
-    #[arg(short, long, default_value = "ezkify")]
-    key: compact_str::CompactString,
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[arg(short, long = "key", default_value = "ezkify", value_name = "panel", help = "A panel to scrape, from the registry (repeatable, scraped concurrently), or any name with --url to detect its layout")]
+    keys: Vec<compact_str::CompactString>,
+    #[arg(long, help = "Scrape every panel of the registry")]
+    all: bool,
+    #[arg(short, long, help = "The services page (or API endpoint) of the only panel, instead of the one in the registry")]
     url: Option<String>,
+    #[arg(long, help = "Read the services from the panel's reseller API instead of its services page")]
+    api: bool,
+    #[arg(long, env = "USCR_API_KEY", hide_env_values = true, value_name = "key", help = "The API key of the panels without a USCR_API_KEY_<PANEL>, for --api")]
+    api_key: Option<String>,
+    #[arg(long, value_name = "file", help = "A panel registry (JSON) to use instead of the built-in one")]
+    panels: Option<std::path::PathBuf>,
+    #[arg(long, value_name = "file", help = "An FX table (JSON, US dollars per unit) to use instead of the built-in one")]
+    fx: Option<std::path::PathBuf>,
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+    #[command(flatten)]
+    sink: uscr::sink::SinkArgs,
+    #[command(flatten)]
+    rate: uscr::scrape::limit::RateArgs,
+    #[command(flatten)]
+    archive: uscr::scrape::archive::ArchiveArgs,
+    #[arg(long, value_name = "dir", help = "Parse the pages of this panel archived in this directory instead of fetching")]
+    replay: Option<std::path::PathBuf>,
 }
 
-async fn insert_category(
-    conn: &mut tokio_postgres::Client,
-    key: &str,
-    cid: i64,
-    desc: &str,
-) -> uscr::db::DBResult<()> {
-    const SQL: &str = "insert into ezkify.categories (key, id, \"desc\") values ($1, $2, $3) on conflict (key, id) do update set \"desc\" = excluded.desc";
-    let stmt = conn.prepare_static(SQL.into()).await?;
-    conn.execute(&stmt, &[&key, &cid, &desc]).await?;
-    Ok(())
+#[derive(clap::Subcommand)]
+enum Commands {
+    /// Rank the panels by price within each class of service, from the database
+    Report {
+        #[arg(long, value_name = "YYYY-MM-DD", help = "Use the last snapshot of each panel on or before this day, default the latest")]
+        date: Option<String>,
+        #[arg(long, help = "Only this platform, e.g. Instagram")]
+        platform: Option<String>,
+        #[arg(long, default_value_t = 5, value_name = "n", help = "Panels shown per class")]
+        top: usize,
+        #[arg(long, default_value_t = 2, value_name = "n", help = "Only the classes sold by at least this many panels")]
+        min_panels: usize,
+    },
+    /// Find the services resold across panels and store them into ezkify.resellers
+    Resellers {
+        #[arg(long, value_name = "YYYY-MM-DD", help = "Use the last snapshot of each panel on or before this day, default the latest")]
+        date: Option<String>,
+        #[arg(long, default_value_t = 0.8, value_name = "0..1", help = "The similarity of descriptions (or names) from which two services with the same limits are the same")]
+        similarity: f64,
+        #[arg(short = 'n', long, help = "Only print the chains, do not store them")]
+        dry_run: bool,
+    },
+    /// Report how the services of a panel changed between two snapshots, from the database
+    Diff {
+        #[arg(short, long, value_name = "panel")]
+        key: String,
+        #[arg(default_value_t = 1, help = "The older snapshot, counting back from the latest (0)")]
+        from: usize,
+        #[arg(default_value_t = 0, help = "The newer snapshot")]
+        to: usize,
+    },
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Category {
+    key: compact_str::CompactString,
+    id: i64,
+    desc: String,
 }
 
+impl uscr::sink::Record for Category {
+    const TABLE: &'static str = "ezkify.categories";
+    const KEY: &'static [&'static str] = &["key", "id"];
+
+    fn bulk(rows: &[Self]) -> uscr::db::bulk::Bulk<'_, Self> {
+        use tokio_postgres::types::Type;
+
+        uscr::db::bulk::Bulk::new(Self::TABLE, rows)
+            .column("key", Type::TEXT, |x| &*x.key)
+            .column("id", Type::INT8, |x| x.id)
+            .column("desc", Type::TEXT, |x| &*x.desc)
+            .on_conflict_update(&["key", "id"], &["desc"])
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
 struct Item {
     id: i64,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
     time: std::time::SystemTime,
+    #[serde(rename = "category_id")]
     cid: i64,
     service: String,
+    /// In US dollars.
     rate_per_1k: f64,
+    /// As shown by the panel.
+    rate: f64,
+    currency: compact_str::CompactString,
     min_order: i64,
     max_order: i64,
     description: String,
+    #[serde(flatten)]
+    attrs: attrs::Attributes,
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn insert_db(
-    conn: &mut tokio_postgres::Client,
-    key: &str,
+/// An [`Item`] of the panel `key`.
+#[derive(Debug, serde::Serialize)]
+struct KeyedItem {
+    key: compact_str::CompactString,
+    #[serde(flatten)]
     item: Item,
-) -> uscr::db::DBResult<()> {
-    const SQL: &str = "insert into ezkify.items (key, id, time, category_id, service, rate_per_1k, min_order, max_order, description) values ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
-    let stmt = conn.prepare_static(SQL.into()).await?;
-    conn.execute(&stmt, &[
-        &key,
-        &item.id,
-        &item.time,
-        &item.cid,
-        &item.service,
-        &item.rate_per_1k,
-        &item.min_order,
-        &item.max_order,
-        &item.description,
-    ])
-    .await?;
-    Ok(())
 }
 
-struct ScrapeConfig {
-    category_class: &'static str,
-    parse_item: fn(row: scraper::ElementRef) -> anyhow::Result<Item>,
-    table_selector: scraper::Selector,
+impl uscr::sink::Record for KeyedItem {
+    const TABLE: &'static str = "ezkify.items";
+    const KEY: &'static [&'static str] = &["key", "id", "time"];
+
+    fn bulk(rows: &[Self]) -> uscr::db::bulk::Bulk<'_, Self> {
+        use tokio_postgres::types::Type;
+
+        uscr::db::bulk::Bulk::new(Self::TABLE, rows)
+            .column("key", Type::TEXT, |x| &*x.key)
+            .column("id", Type::INT8, |x| x.item.id)
+            .column("time", Type::TIMESTAMP, |x| x.item.time)
+            .column("category_id", Type::INT8, |x| x.item.cid)
+            .column("service", Type::TEXT, |x| &*x.item.service)
+            .column("rate_per_1k", Type::FLOAT8, |x| x.item.rate_per_1k)
+            .column("rate", Type::FLOAT8, |x| x.item.rate)
+            .column("currency", Type::TEXT, |x| &*x.item.currency)
+            .column("min_order", Type::INT8, |x| x.item.min_order)
+            .column("max_order", Type::INT8, |x| x.item.max_order)
+            .column("description", Type::TEXT, |x| &*x.item.description)
+            .column("refill", Type::BOOL, |x| x.item.attrs.refill)
+            .column("refill_days", Type::INT4, |x| x.item.attrs.refill_days)
+            .column("cancel", Type::BOOL, |x| x.item.attrs.cancel)
+            .column("drip_feed", Type::BOOL, |x| x.item.attrs.drip_feed)
+            .column("average_seconds", Type::INT8, |x| x.item.attrs.average_seconds)
+            // a replayed page takes the place of the stored one
+            .on_conflict_update(&["key", "id", "time"], &[
+                "category_id", "service", "rate_per_1k", "rate", "currency", "min_order", "max_order",
+                "description", "refill", "refill_days", "cancel", "drip_feed", "average_seconds",
+            ])
+    }
 }
 
-fn get_config(key: &str) -> ScrapeConfig {
-    let category_class = match key {
-        "smmrapid" => "category",
-        "dripfeedpanel" => "cat-name servicescategory",
-        _ => "services-list-category-title",
-    };
+/// A snapshot of the panel `key` was stored: its items are all in
+/// `ezkify.items` at `time`, and the database diffs them against the previous
+/// snapshot into `ezkify.service` and `ezkify.service_event`, again when the
+/// page is replayed.
+#[derive(Debug, serde::Serialize)]
+struct Snapshot {
+    key: compact_str::CompactString,
+    #[serde(serialize_with = "uscr::sink::timestamp")]
+    time: std::time::SystemTime,
+}
 
-    let parse_item = match key {
-        // "smmrapid" => parse_item::smmrapid::parse,
-        "dripfeedpanel" => parse_item::dripfeedpanel::parse,
-        _ => parse_item::ezkify::parse,
-    };
+impl uscr::sink::Record for Snapshot {
+    const TABLE: &'static str = "ezkify.snapshot";
+    const KEY: &'static [&'static str] = &["key", "time"];
+
+    fn bulk(rows: &[Self]) -> uscr::db::bulk::Bulk<'_, Self> {
+        use tokio_postgres::types::Type;
+
+        uscr::db::bulk::Bulk::new(Self::TABLE, rows)
+            .column("key", Type::TEXT, |x| &*x.key)
+            .column("time", Type::TIMESTAMP, |x| x.time)
+            // the update fires the diff for the re-parsed items
+            .on_conflict_update(&["key", "time"], &["time"])
+    }
+}
+
+/// Parses a whole services page of the panel `key`, or the answer of its API
+/// if `api`.
+struct ServicesParser<'a> {
+    key: compact_str::CompactString,
+    /// [`None`] if the panel is not in `registry`: the layout of its page is
+    /// then detected among those of `registry`.
+    panel: Option<&'a panel::Panel>,
+    registry: &'a std::collections::BTreeMap<compact_str::CompactString, panel::Panel>,
+    api: bool,
+    fx: &'a money::Fx,
+}
 
-    let table_selector_s = match key {
-        "smmrapid" => ".services>.container-xxl",
-        "dripfeedpanel" => "#service-table>tbody",
-        _ => "#service-tbody",
+impl uscr::scrape::pipeline::Parser<uscr::scrape::pipeline::Fetched<()>> for ServicesParser<'_> {
+    /// The snapshot is written last, and only if there are items: a page
+    /// whose items all failed to parse does not remove every service.
+    type Output = ((Vec<Category>, Vec<KeyedItem>), Vec<Snapshot>);
+
+    fn parse(&self, page: &uscr::scrape::pipeline::Fetched<()>) -> anyhow::Result<Self::Output> {
+        log::info!(target: &self.key, "fetching finished: {} bytes", page.body.len());
+        let output = if self.api {
+            let currency = self.panel.map_or("USD", |panel| &panel.currency);
+            api::services(&self.key, currency, self.fx, &page.body, page.date)?
+        } else if let Some(panel) = self.panel {
+            panel.services(&self.key, self.fx, &page.body, page.date)?
+        } else {
+            let (layout, panel) = panel::detect(self.registry, self.fx, &page.body, page.date)?;
+            log::info!(target: &self.key, "layout detected: \x1b[36m{layout}\x1b[0m");
+            panel.services(&self.key, self.fx, &page.body, page.date)?
+        };
+        log::info!(target: &self.key, "parsing finished.");
+        let snapshot = if output.1.is_empty() { Vec::new() } else { vec![Snapshot { key: self.key.clone(), time: page.date }] };
+        Ok((output, snapshot))
+    }
+}
+
+impl Args {
+    /// The API key of the panel `key`: `USCR_API_KEY_<KEY>` (upper case, `_`
+    /// for anything not alphanumeric), else `--api-key`.
+    fn api_key(&self, key: &str) -> Option<String> {
+        let name = key.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect::<String>();
+        std::env::var(format!("USCR_API_KEY_{name}")).ok().or_else(|| self.api_key.clone())
+    }
+}
+
+/// Scrapes (or replays) the panel `key` on its own, logging under `key`.
+/// `panel` is its entry of `registry`, if any.
+async fn run(
+    args: &Args,
+    key: &compact_str::CompactString,
+    panel: Option<&panel::Panel>,
+    registry: &std::collections::BTreeMap<compact_str::CompactString, panel::Panel>,
+    fx: &money::Fx,
+    sink: &uscr::sink::AnySink,
+    limiter: &uscr::scrape::limit::Limiter,
+) -> anyhow::Result<uscr::scrape::pipeline::Stats> {
+    use uscr::scrape::{
+        archive::{Archived, Replay},
+        pipeline::{Fetched, Pipeline, Requests},
+        retry::Retry,
     };
 
-    ScrapeConfig {
-        category_class,
-        parse_item,
-        table_selector: scraper::Selector::parse(table_selector_s).unwrap(),
+    let parser = ServicesParser { key: key.clone(), panel, registry, api: args.api, fx };
+
+    if let Some(ref dir) = args.replay {
+        let mut entries = uscr::scrape::archive::entries(dir)?;
+        entries.retain(|entry| entry.archived_by(key) && entry.status == Some(200));
+        let source = Replay::new(dir, entries, |page: Archived| {
+            Some(Ok(Fetched { tag: (), date: page.date(), body: page.body }))
+        });
+        return Ok(Pipeline::new(key, sink).run(source, &parser).await);
     }
+
+    let url = match (&args.url, panel) {
+        (Some(url), _) => url.parse()?,
+        (None, Some(panel)) if args.api => panel.api()?,
+        (None, Some(panel)) => panel.url.parse()?,
+        (None, None) => anyhow::bail!("unknown panel {key:?} needs --url"),
+    };
+    let client = uscr::scrape::simple();
+    let archive = args.archive.open(key)?;
+    let retry = Retry::new(Some(limiter)).archive(archive.as_ref());
+    let mut source = Requests::new(&retry);
+    if args.api {
+        let Some(api_key) = args.api_key(key) else {
+            anyhow::bail!("--api needs an API key (--api-key or USCR_API_KEY_<PANEL>)");
+        };
+        source.push((), api::request(&client, url, &api_key));
+    } else {
+        source.push((), client.get(url));
+    }
+
+    log::info!(target: key, "start fetching ...");
+    Ok(Pipeline::new(key, sink).run(source, &parser).await)
 }
 
 #[tokio::main]
-#[allow(clippy::too_many_lines)]
 async fn main() -> anyhow::Result<()> {
     use clap::Parser;
-    use parse_item::{CID, GLOBAL_DATE};
-    use std::sync::atomic::Ordering;
 
     pretty_env_logger::init_timed();
-    uscr::db::init_db().await;
 
-    let args = Args::parse();
-    let client = uscr::scrape::simple();
+    let mut args = Args::parse();
+    if let Some(ref command) = args.command {
+        uscr::db::init_db(args.db.config()?).await?;
+        return match *command {
+            Commands::Report { ref date, ref platform, top, min_panels } => report::report(date.as_deref(), platform.as_deref(), top, min_panels).await,
+            Commands::Resellers { ref date, similarity, dry_run } => resellers::resellers(date.as_deref(), similarity, dry_run).await,
+            Commands::Diff { ref key, from, to } => diff::diff(key, from, to).await,
+        };
+    }
+    let registry = match args.panels {
+        Some(ref path) => panel::registry(&std::fs::read_to_string(path)?)?,
+        None => panel::registry(panel::BUILTIN)?,
+    };
+    let keys = if args.all {
+        registry.keys().cloned().collect()
+    } else {
+        let mut keys = args.keys.clone();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    };
+    if args.url.is_some() && keys.len() != 1 {
+        anyhow::bail!("--url needs a single panel");
+    }
+    let mut panels = Vec::with_capacity(keys.len());
+    for key in keys {
+        let panel = registry.get(&key);
+        if panel.is_none() && args.url.is_none() && args.replay.is_none() {
+            anyhow::bail!(
+                "unknown panel {key:?}, known: {:?} (give its services page with --url to detect its layout)",
+                registry.keys().collect::<Vec<_>>(),
+            );
+        }
+        panels.push((key, panel));
+    }
 
-    tracing::info!(target: "main", "start fetching ...");
-    let res = client
-        .get(args.url.unwrap_or_else(|| format!("https://{}.com/services", args.key)))
-        .send()
-        .await?;
-
-    *GLOBAL_DATE.write() = httpdate::parse_http_date(
-        res.headers()
-            .get(reqwest::header::DATE)
-            .ok_or_else(|| anyhow::anyhow!("no date"))?
-            .to_str()?,
-    )?;
-
-    let res = res.text().await?;
-    tracing::info!(target: "main", "fetching finished: {} bytes", res.len());
-    let html = scraper::Html::parse_document(&res);
-    tracing::info!(target: "main", "parsing finished.");
-
-    let config = get_config(&args.key);
-    let mut conn = uscr::db::get_connection().await?;
-
-    let tbody = html
-        .select(&config.table_selector)
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("element not found"))?;
-
-    if args.key == "smmrapid" {
-        for category in tbody.child_elements() {
-            use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
-
-            if category.attr("class") != Some(config.category_class) {
-                continue;
+    let fx = match args.fx {
+        Some(ref path) => money::Fx::from_json(&std::fs::read_to_string(path)?)?,
+        None => money::Fx::from_json(money::BUILTIN_FX)?,
+    };
+
+    let sink = args.sink.open(&args.db).await?;
+    // panels are on different hosts, so they are paced independently
+    let limiter = uscr::scrape::limit::Limiter::new(&[], core::mem::take(&mut args.rate.rates));
+    let (args, registry, fx, sink, limiter) = (&args, &registry, &fx, &sink, &limiter);
+    let results = futures_util::future::join_all(panels.into_iter().map(|(key, panel)| async move {
+        let result = run(args, &key, panel, registry, fx, sink, limiter).await;
+        (key, result)
+    }))
+    .await;
+
+    let mut failed = 0;
+    for (key, result) in &results {
+        match result {
+            Ok(stats) if stats.failed + stats.unparsed + stats.unstored == 0 => tracing::info!(target: "main", "\x1b[36m{key}\x1b[0m: {stats}"),
+            Ok(stats) => {
+                failed += 1;
+                tracing::error!(target: "main", "\x1b[31m{key}\x1b[0m: {stats}");
             }
-            let [category, items] = category
-                .child_elements()
-                .next_chunk()
-                .map_err(|e| anyhow::anyhow!("category error: {e:?}"))?;
-            let desc = category.text().map(str::trim).collect::<String>();
-            let cid = BuildHasherDefault::<DefaultHasher>::default()
-                .hash_one(&desc)
-                .cast_signed();
-
-            insert_category(&mut conn, &args.key, cid, &desc).await?;
-            CID.store(cid, Ordering::SeqCst);
-
-            for [item, modal] in items.child_elements().array_chunks() {
-                match parse_item::smmrapid::parse(item, modal) {
-                    Ok(item) => insert_db(&mut conn, &args.key, item).await?,
-                    Err(e) => tracing::error!(?e),
-                }
-            }
-        }
-    } else {
-        for row in tbody.child_elements() {
-            if row.attr("class") == Some(config.category_class) {
-                let cid = row
-                    .attr("data-filter-table-category-id")
-                    .and_then(|x| x.parse().ok())
-                    .unwrap_or(-1);
-                let desc = row.text().map(str::trim).collect::<String>();
-                insert_category(&mut conn, &args.key, cid, &desc).await?;
-                CID.store(cid, Ordering::SeqCst);
-            } else {
-                match (config.parse_item)(row) {
-                    Ok(item) => insert_db(&mut conn, &args.key, item).await?,
-                    Err(e) => tracing::error!(?e),
-                }
+            Err(e) => {
+                failed += 1;
+                tracing::error!(target: "main", "\x1b[31m{key}\x1b[0m: {e:?}");
             }
         }
     }
+    if failed != 0 {
+        anyhow::bail!("{failed} of {} panels failed", results.len());
+    }
 
     Ok(())
 }
 
 /*
 
-bin/ezkify -k ezkify
-bin/ezkify -k smmrapid
-bin/ezkify -k dripfeedpanel
+bin/ezkify --all
+bin/ezkify -k ezkify -k smmrapid -k dripfeedpanel
 bin/ezkify -k fullsmm -u https://panel.fullsmm.com/services
-bin/ezkify -k smmcost
-bin/ezkify -k n1panel
+bin/ezkify -k somepanel -u https://somepanel.com/services
+bin/ezkify report --date 2026-10-01 --platform instagram
+bin/ezkify resellers --similarity 0.9
+bin/ezkify diff -k smmrapid
 
 */
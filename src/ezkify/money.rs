@@ -0,0 +1,98 @@
+use compact_str::CompactString;
+use hashbrown::HashMap;
+use serde::Deserialize;
+
+/// The FX table used unless `--fx` gives another one, see [`Fx`].
+pub const BUILTIN_FX: &str = include_str!("fx.json");
+
+/// An amount in a currency (ISO 4217 code).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: CompactString,
+}
+
+/// Currency signs and their codes. Longer ones first, as `$` ends several.
+const SIGNS: &[(&str, &str)] = &[
+    ("US$", "USD"),
+    ("R$", "BRL"),
+    ("$", "USD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("₽", "RUB"),
+    ("РУБ", "RUB"),
+    ("РУБ.", "RUB"),
+    ("₹", "INR"),
+    ("RS", "INR"),
+    ("RS.", "INR"),
+    ("₺", "TRY"),
+    ("₦", "NGN"),
+    ("৳", "BDT"),
+    ("RP", "IDR"),
+];
+
+/// The currency named by `marker`, a sign or a code.
+fn currency(marker: &str) -> Option<CompactString> {
+    let marker = marker.to_uppercase();
+    if marker.len() == 3 && marker.bytes().all(|b| b.is_ascii_uppercase()) {
+        return Some(marker.into());
+    }
+    SIGNS.iter().find(|(sign, _)| *sign == marker).map(|(_, code)| CompactString::from(*code))
+}
+
+/// `digits` with any grouping and either decimal separator, e.g. `1 234.5`,
+/// `1.234,5`, `1,234`, `0,50`.
+fn number(digits: &str) -> Option<f64> {
+    let digits = digits.replace(|c: char| c.is_whitespace() || c == '\'', "");
+    let normalized = match (digits.rfind('.'), digits.rfind(',')) {
+        // the last separator is the decimal one
+        (Some(dot), Some(comma)) if dot > comma => digits.replace(',', ""),
+        (Some(_), Some(_)) => digits.replace('.', "").replace(',', "."),
+        (Some(_), None) if digits.matches('.').count() > 1 => digits.replace('.', ""),
+        (_, None) => digits,
+        (None, Some(_)) if digits.matches(',').count() > 1 => digits.replace(',', ""),
+        // a single comma groups thousands unless it cannot (`0,500`, `1,5`)
+        (None, Some(comma)) => {
+            let int = &digits[..comma];
+            if digits.len() - comma == 4 && !int.is_empty() && int != "0" {
+                digits.replace(',', "")
+            } else {
+                digits.replace(',', ".")
+            }
+        }
+    };
+    normalized.parse().ok()
+}
+
+/// Parses a price such as `$1.20`, `1,20 €`, `₹ 1 200`, `0.5 USD`; without
+/// a currency marker it is in `default`.
+pub fn parse(text: &str, default: &str) -> Option<Money> {
+    let text = text.trim();
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let end = text.rfind(|c: char| c.is_ascii_digit())? + 1;
+    // separators may only appear between digits
+    let marker = format!("{}{}", &text[..start], &text[end..]);
+    let marker = marker.trim();
+    let currency = if marker.is_empty() { default.into() } else { currency(marker)? };
+    Some(Money { amount: number(&text[start..end])?, currency })
+}
+
+/// US dollars per unit of each currency, e.g. `{"EUR": 1.16}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Fx(HashMap<CompactString, f64>);
+
+impl Fx {
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// `money` in US dollars, [`None`] if its currency is not in the table.
+    pub fn usd(&self, money: &Money) -> Option<f64> {
+        if money.currency == "USD" {
+            Some(money.amount)
+        } else {
+            self.0.get(&money.currency).map(|rate| money.amount * rate)
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use core::fmt;
+use std::{collections::BTreeMap, time::SystemTime};
+
+use compact_str::CompactString;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Deserializer};
+
+use super::{
+    Category, Item, KeyedItem,
+    attrs::{self, Attributes},
+    money::{self, Fx},
+};
+
+/// The panels known out of the box, see [`Panel`] for the format.
+pub const BUILTIN: &str = include_str!("panels.json");
+
+/// How to scrape the services page of an SMM panel, one entry of the panel
+/// registry (a JSON object keyed by panel name).
+///
+/// All selectors but `table` are relative to the element they are applied to
+/// (use `:scope>` for its children).
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Panel {
+    /// The services page.
+    pub url: String,
+    /// The reseller API endpoint, `/api/v2` of `url` if unset.
+    #[serde(default)]
+    pub api: Option<String>,
+    /// The currency of the rates without a currency sign or code, and of the
+    /// API, `USD` if unset.
+    #[serde(default = "usd")]
+    pub currency: CompactString,
+    /// The element holding the categories and items, in the whole page.
+    #[serde(deserialize_with = "selector")]
+    pub table: Selector,
+    /// The category headings, in `table`. Each applies to the items after it.
+    #[serde(deserialize_with = "selector")]
+    pub category: Selector,
+    /// The items, in `table`; an element that is also a category heading is
+    /// not an item.
+    #[serde(deserialize_with = "selector")]
+    pub item: Selector,
+    /// The cells of an item, in the item.
+    #[serde(deserialize_with = "selector")]
+    pub cells: Selector,
+    pub columns: Columns,
+    /// The attribute holding the item id, looked up on the id cell then on
+    /// the item; the text of the id cell if unset.
+    #[serde(default)]
+    pub id_attr: Option<String>,
+    /// The description, in its cell. Its text and `<br>`s are kept, not those
+    /// of its children.
+    #[serde(deserialize_with = "selector")]
+    pub description: Selector,
+    pub category_id: CategoryId,
+}
+
+/// Indices into the cells of an item.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    pub id: usize,
+    pub service: usize,
+    /// Per thousand, in any currency and number format [`money::parse`] knows.
+    pub rate: usize,
+    /// Thousands may be separated by whitespace or commas.
+    pub min: usize,
+    pub max: usize,
+    /// [`None`] if the description is in the element after the item (e.g. a
+    /// modal) instead.
+    #[serde(default)]
+    pub description: Option<usize>,
+    /// The columns below are optional; what they would say is otherwise
+    /// looked for in the description, see [`Attributes::from_description`].
+    /// `30 days`, `Lifetime`, `No`, ...
+    #[serde(default)]
+    pub refill: Option<usize>,
+    /// `Yes` / `No` and the like.
+    #[serde(default)]
+    pub cancel: Option<usize>,
+    #[serde(default)]
+    pub drip_feed: Option<usize>,
+    /// `12 hours`, `1h 30m`, ...
+    #[serde(default)]
+    pub average_time: Option<usize>,
+}
+
+/// Where the id of a category comes from.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum CategoryId {
+    /// An attribute of the heading (-1 if missing).
+    Attr(String),
+    /// [`uscr::util::stable_id`] of the heading text, for panels without ids.
+    Hash,
+}
+
+const fn usd() -> CompactString {
+    CompactString::const_new("USD")
+}
+
+fn selector<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Selector, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Selector::parse(&s).map_err(|e| serde::de::Error::custom(format_args!("bad selector {s:?}: {e}")))
+}
+
+/// Parses a panel registry.
+pub fn registry(json: &str) -> anyhow::Result<BTreeMap<CompactString, Panel>> {
+    Ok(serde_json::from_str(json)?)
+}
+
+/// A min/max cell, with the thousands separated by whitespace, commas, dots
+/// or apostrophes.
+fn count(cell: ElementRef) -> Option<i64> {
+    cell.text()
+        .map(|c| c.replace(|c: char| c.is_whitespace() || matches!(c, ',' | '.' | '\''), ""))
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// The text directly in `elem`, with `<br>` as line breaks.
+fn description(elem: ElementRef) -> String {
+    let mut description = String::new();
+    for node in elem.children() {
+        match node.value() {
+            scraper::Node::Text(text) => description.push_str(text),
+            scraper::Node::Element(elem) if elem.name() == "br" => description.push('\n'),
+            _ => (),
+        }
+    }
+    description
+}
+
+impl Panel {
+    /// The reseller API endpoint.
+    pub fn api(&self) -> anyhow::Result<reqwest::Url> {
+        Ok(match self.api {
+            Some(ref api) => api.parse()?,
+            None => reqwest::Url::parse(&self.url)?.join("/api/v2")?,
+        })
+    }
+
+    /// The categories and items of the services page `body` of the panel
+    /// `key`, fetched at `time`, with the rates converted by `fx`.
+    pub fn services(&self, key: &CompactString, fx: &Fx, body: &str, time: SystemTime) -> anyhow::Result<(Vec<Category>, Vec<KeyedItem>)> {
+        let html = Html::parse_document(body);
+        let (categories, items) = self.parse(&html, key, fx, time)?;
+        let items = items
+            .into_iter()
+            .filter_map(|item| item.inspect_err(|e| tracing::error!(?e)).ok())
+            .collect();
+        Ok((categories, items))
+    }
+
+    /// How well the page `html` fits the layout of this panel, [`None`] if it
+    /// has no `table`.
+    pub fn fingerprint(&self, html: &Html, fx: &Fx, time: SystemTime) -> Option<Fingerprint> {
+        let (categories, items) = self.parse(html, &CompactString::default(), fx, time).ok()?;
+        Some(Fingerprint {
+            categories: categories.len(),
+            items: items.len(),
+            parsed: items.iter().filter(|x| x.is_ok()).count(),
+        })
+    }
+
+    /// The categories, and the items or why they could not be parsed.
+    fn parse(&self, html: &Html, key: &CompactString, fx: &Fx, time: SystemTime) -> anyhow::Result<(Vec<Category>, Vec<anyhow::Result<KeyedItem>>)> {
+        let mut categories = Vec::new();
+        let mut items = Vec::new();
+
+        let table = html
+            .select(&self.table)
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("element not found"))?;
+
+        // both kinds, in document order
+        let headings = table.select(&self.category).map(|e| e.id()).collect::<hashbrown::HashSet<_>>();
+        let rows = table.select(&self.item).map(|e| e.id()).collect::<hashbrown::HashSet<_>>();
+
+        let mut cid = 0;
+        for elem in table.descendants().filter_map(ElementRef::wrap) {
+            if headings.contains(&elem.id()) {
+                let desc = elem.text().map(str::trim).collect::<String>();
+                cid = match self.category_id {
+                    CategoryId::Attr(ref attr) => elem.attr(attr).and_then(|x| x.parse().ok()).unwrap_or(-1),
+                    CategoryId::Hash => uscr::util::stable_id(&desc),
+                };
+                categories.push(Category { key: key.clone(), id: cid, desc });
+            } else if rows.contains(&elem.id()) {
+                items.push(self.item(elem, cid, fx, time).map(|item| KeyedItem { key: key.clone(), item }));
+            }
+        }
+
+        Ok((categories, items))
+    }
+
+    /// The item `elem` of the category `cid`.
+    fn item(&self, elem: ElementRef, cid: i64, fx: &Fx, time: SystemTime) -> anyhow::Result<Item> {
+        let cells = elem.select(&self.cells).collect::<Vec<_>>();
+        let columns = &self.columns;
+        let cell = |i: usize| cells.get(i).copied().ok_or_else(|| anyhow::anyhow!("child error: no cell {i} in {}", elem.html()));
+
+        let id_cell = cell(columns.id)?;
+        let id = match self.id_attr {
+            Some(ref attr) => id_cell.attr(attr).or_else(|| elem.attr(attr)).and_then(|x| x.parse().ok()),
+            None => id_cell.text().map(str::trim).collect::<String>().parse().ok(),
+        };
+        let Some(id) = id else {
+            anyhow::bail!("id error: {}", id_cell.html());
+        };
+
+        let service = cell(columns.service)?.text().map(str::trim).collect();
+        let rate_cell = cell(columns.rate)?;
+        let Some(rate) = money::parse(&rate_cell.text().collect::<String>(), &self.currency) else {
+            anyhow::bail!("rate error: {}", rate_cell.html());
+        };
+        let Some(rate_per_1k) = fx.usd(&rate) else {
+            anyhow::bail!("no FX rate for {}: {}", rate.currency, rate_cell.html());
+        };
+        let Some(min_order) = count(cell(columns.min)?) else {
+            anyhow::bail!("min_order error: {}", cell(columns.min)?.html());
+        };
+        let Some(max_order) = count(cell(columns.max)?) else {
+            anyhow::bail!("max_order error: {}", cell(columns.max)?.html());
+        };
+
+        let within = match columns.description {
+            Some(i) => Some(cell(i)?),
+            None => elem.next_siblings().find_map(ElementRef::wrap),
+        };
+        let description = within
+            .and_then(|x| x.select(&self.description).next())
+            .map(description)
+            .unwrap_or_default();
+
+        let text = |i: Option<usize>| -> anyhow::Result<Option<String>> {
+            Ok(match i {
+                Some(i) => Some(cell(i)?.text().collect::<String>()),
+                None => None,
+            })
+        };
+        let (refill, refill_days) = text(columns.refill)?.map_or((None, None), |x| attrs::refill(&x));
+        let attrs = Attributes {
+            refill,
+            refill_days,
+            cancel: text(columns.cancel)?.and_then(|x| attrs::flag(&x)),
+            drip_feed: text(columns.drip_feed)?.and_then(|x| attrs::flag(&x)),
+            average_seconds: text(columns.average_time)?.and_then(|x| attrs::seconds(&x)),
+        }
+        .or(Attributes::from_description(&description));
+
+        Ok(Item {
+            id,
+            time,
+            cid,
+            service,
+            rate_per_1k,
+            rate: rate.amount,
+            currency: rate.currency,
+            min_order,
+            max_order,
+            description,
+            attrs,
+        })
+    }
+}
+
+/// What a layout finds in a services page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub categories: usize,
+    pub items: usize,
+    /// The items whose cells all parse.
+    pub parsed: usize,
+}
+
+impl Fingerprint {
+    /// Whether the layout can be used: some items, most of them parsed.
+    pub const fn fits(&self) -> bool {
+        self.parsed > 0 && self.parsed * 2 >= self.items
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} categories, {} of {} items parsed", self.categories, self.parsed, self.items)
+    }
+}
+
+/// The panel of `registry` whose layout fits the services page `body` best
+/// (the most items parsed, then the most categories, then the first), for a
+/// panel not in the registry; its rates without a currency are in US
+/// dollars.
+pub fn detect<'r>(registry: &'r BTreeMap<CompactString, Panel>, fx: &Fx, body: &str, time: SystemTime) -> anyhow::Result<(&'r CompactString, Panel)> {
+    let html = Html::parse_document(body);
+    let fingerprints = registry
+        .iter()
+        .map(|(key, panel)| (key, panel, panel.fingerprint(&html, fx, time)))
+        .collect::<Vec<_>>();
+
+    let best = fingerprints
+        .iter()
+        .rev()
+        .filter_map(|&(key, panel, fp)| Some((key, panel, fp.filter(Fingerprint::fits)?)))
+        .max_by_key(|(_, _, fp)| (fp.parsed, fp.categories));
+    if let Some((key, panel, _)) = best {
+        let mut panel = panel.clone();
+        panel.currency = usd();
+        return Ok((key, panel));
+    }
+
+    let tried = fingerprints
+        .iter()
+        .map(|(key, _, fp)| match fp {
+            Some(fp) => format!("{key}: {fp}"),
+            None => format!("{key}: no table"),
+        })
+        .collect::<Vec<_>>();
+    anyhow::bail!("no known layout fits the page ({})", tried.join("; "));
+}
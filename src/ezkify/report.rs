@@ -0,0 +1,98 @@
+use hashbrown::HashMap;
+use std::collections::BTreeMap;
+
+use uscr::db::get_connection;
+
+use super::taxonomy::{Class, classify};
+
+/// An item of a panel, in the snapshot of the report.
+#[derive(Clone, Debug)]
+pub struct Offer {
+    pub key: String,
+    pub id: i64,
+    pub service: String,
+    pub rate_per_1k: f64,
+    pub min_order: i64,
+    pub max_order: i64,
+}
+
+/// Groups `offers` by class and ranks the panels of each class by their
+/// cheapest offer, keeping the classes sold by at least `min_panels` panels.
+pub fn rank<I: IntoIterator<Item = (Class, Offer)>>(offers: I, min_panels: usize) -> Vec<(Class, Vec<Offer>)> {
+    let mut classes = BTreeMap::<Class, HashMap<String, Offer>>::new();
+    for (class, offer) in offers {
+        let cheapest = classes.entry(class).or_default();
+        match cheapest.get(&offer.key) {
+            Some(o) if o.rate_per_1k <= offer.rate_per_1k => (),
+            _ => {
+                cheapest.insert(offer.key.clone(), offer);
+            }
+        }
+    }
+    classes
+        .into_iter()
+        .filter(|(_, panels)| panels.len() >= min_panels)
+        .map(|(class, panels)| {
+            let mut panels = panels.into_values().collect::<Vec<_>>();
+            panels.sort_by(|a, b| a.rate_per_1k.total_cmp(&b.rate_per_1k).then_with(|| a.key.cmp(&b.key)));
+            (class, panels)
+        })
+        .collect()
+}
+
+/// Prints, for each class of service, the panels ranked by price per 1k in
+/// the last snapshot of each panel taken on or before `date` (`YYYY-MM-DD`,
+/// default the latest).
+pub async fn report(date: Option<&str>, platform: Option<&str>, top: usize, min_panels: usize) -> anyhow::Result<()> {
+    const ITEMS: &str = "with at as (select key, max(\"time\") as \"time\" from ezkify.items where \"time\" < $1::text::date + 1 group by key) \
+        select i.key, i.id, i.service, c.\"desc\", i.rate_per_1k, i.min_order, i.max_order, i.refill, i.\"time\" \
+        from ezkify.items as i join at using (key, \"time\") \
+        join ezkify.categories as c on c.key = i.key and c.id = i.category_id \
+        order by i.key, i.id";
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(ITEMS.into()).await?;
+    let rows = conn.query(&stmt, &[&date.unwrap_or("infinity")]).await?;
+
+    let mut snapshots = BTreeMap::new();
+    let mut unclassified = 0;
+    let mut offers = Vec::with_capacity(rows.len());
+    for row in rows {
+        let key: String = row.try_get(0)?;
+        let service: String = row.try_get(2)?;
+        let category: &str = row.try_get(3)?;
+        snapshots.insert(key.clone(), row.try_get::<_, std::time::SystemTime>(8)?);
+        let Some(class) = classify(&service, category, row.try_get(7)?) else {
+            unclassified += 1;
+            continue;
+        };
+        if platform.is_some_and(|p| !p.eq_ignore_ascii_case(class.platform)) {
+            continue;
+        }
+        offers.push((class, Offer {
+            key,
+            id: row.try_get(1)?,
+            service,
+            rate_per_1k: row.try_get(4)?,
+            min_order: row.try_get(5)?,
+            max_order: row.try_get(6)?,
+        }));
+    }
+
+    println!("prices per 1k in USD, at {}", date.unwrap_or("the latest snapshots"));
+    for (key, time) in &snapshots {
+        println!("    {key}: {}", httpdate::fmt_http_date(*time));
+    }
+    let classes = rank(offers, min_panels);
+    for (class, panels) in &classes {
+        println!("\n\x1b[36m{class}\x1b[0m ({} panels)", panels.len());
+        for (i, offer) in panels.iter().take(top).enumerate() {
+            println!(
+                "{:>4}. {:<16} ${:<9.4} #{} {:?} ({} - {})",
+                i + 1, offer.key, offer.rate_per_1k, offer.id, offer.service, offer.min_order, offer.max_order,
+            );
+        }
+    }
+    println!("\n{} classes, {unclassified} items unclassified", classes.len());
+    Ok(())
+}
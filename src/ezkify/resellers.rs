@@ -0,0 +1,237 @@
+use hashbrown::{HashMap, HashSet};
+use std::{collections::BTreeMap, time::SystemTime};
+
+use uscr::db::{bulk::Bulk, get_connection};
+
+/// An item of a panel, in the snapshot analyzed.
+#[derive(Clone, Debug)]
+pub struct Service {
+    pub key: String,
+    pub id: i64,
+    pub time: SystemTime,
+    pub name: String,
+    pub description: String,
+    pub rate_per_1k: f64,
+    pub min_order: i64,
+    pub max_order: i64,
+}
+
+/// `service` looks resold from `upstream`, the cheapest service of its
+/// cluster.
+#[derive(Debug)]
+pub struct Link<'a> {
+    pub service: &'a Service,
+    pub upstream: &'a Service,
+    /// Of the descriptions (or the names, if either has no description), in
+    /// `[0, 1]`.
+    pub similarity: f64,
+    /// The rate of `service` over that of `upstream`.
+    pub markup: f64,
+}
+
+/// The services of the panel `downstream` that look resold from `upstream`.
+#[derive(Debug)]
+pub struct Chain<'a> {
+    pub upstream: &'a str,
+    pub downstream: &'a str,
+    pub services: usize,
+    /// The median of their markups.
+    pub markup: f64,
+}
+
+/// The lower-cased words of `text`.
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let common = a.intersection(b).count();
+    let union = a.len() + b.len() - common;
+    if union == 0 { 0.0 } else { common as f64 / union as f64 }
+}
+
+/// The words of the name and of the description of a service.
+struct Words {
+    name: HashSet<String>,
+    description: HashSet<String>,
+}
+
+impl Words {
+    fn similarity(&self, other: &Self) -> f64 {
+        if self.description.is_empty() || other.description.is_empty() {
+            jaccard(&self.name, &other.name)
+        } else {
+            jaccard(&self.description, &other.description)
+        }
+    }
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Clusters `services` across panels, and links every service of a cluster
+/// to its cheapest one, of another panel.
+///
+/// Two services of different panels are in the same cluster if they have the
+/// same min and max order, and their similarity is at least `threshold`;
+/// clusters are closed under this relation.
+pub fn links(services: &[Service], threshold: f64) -> Vec<Link<'_>> {
+    let words = services
+        .iter()
+        .map(|s| Words { name: words(&s.name), description: words(&s.description) })
+        .collect::<Vec<_>>();
+
+    let mut limits = HashMap::<_, Vec<_>>::new();
+    for (i, s) in services.iter().enumerate() {
+        limits.entry((s.min_order, s.max_order)).or_default().push(i);
+    }
+
+    let mut parent = (0..services.len()).collect::<Vec<_>>();
+    for group in limits.values() {
+        for (n, &i) in group.iter().enumerate() {
+            for &j in &group[n + 1..] {
+                if services[i].key != services[j].key && words[i].similarity(&words[j]) >= threshold {
+                    let (i, j) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[i] = j;
+                }
+            }
+        }
+    }
+
+    let mut clusters = HashMap::<_, Vec<_>>::new();
+    for i in 0..services.len() {
+        clusters.entry(root(&mut parent, i)).or_default().push(i);
+    }
+
+    let mut links = Vec::new();
+    for members in clusters.values().filter(|m| m.len() > 1) {
+        let Some(&up) = members.iter().min_by(|&&a, &&b| {
+            let (a, b) = (&services[a], &services[b]);
+            a.rate_per_1k.total_cmp(&b.rate_per_1k).then_with(|| (&a.key, a.id).cmp(&(&b.key, b.id)))
+        }) else {
+            continue;
+        };
+        let upstream = &services[up];
+        if upstream.rate_per_1k <= 0.0 {
+            continue;
+        }
+        for &i in members {
+            let service = &services[i];
+            if service.key != upstream.key {
+                links.push(Link {
+                    service,
+                    upstream,
+                    similarity: words[i].similarity(&words[up]),
+                    markup: service.rate_per_1k / upstream.rate_per_1k,
+                });
+            }
+        }
+    }
+    links.sort_by(|a, b| (&a.service.key, a.service.id).cmp(&(&b.service.key, b.service.id)));
+    links
+}
+
+/// Groups `links` by pair of panels, the largest first.
+pub fn chains<'a>(links: &[Link<'a>]) -> Vec<Chain<'a>> {
+    let mut pairs = BTreeMap::<_, Vec<f64>>::new();
+    for link in links {
+        pairs.entry((&*link.upstream.key, &*link.service.key)).or_default().push(link.markup);
+    }
+    let mut chains = pairs
+        .into_iter()
+        .map(|((upstream, downstream), mut markups)| {
+            markups.sort_by(f64::total_cmp);
+            let n = markups.len();
+            let markup = if n % 2 == 1 { markups[n / 2] } else { f64::midpoint(markups[n / 2 - 1], markups[n / 2]) };
+            Chain { upstream, downstream, services: n, markup }
+        })
+        .collect::<Vec<_>>();
+    chains.sort_by_key(|c| core::cmp::Reverse(c.services));
+    chains
+}
+
+/// Finds the services resold across panels in the last snapshot of each
+/// panel taken on or before `date` (`YYYY-MM-DD`, default the latest), prints
+/// the chains and, unless `dry_run`, stores the links into
+/// `ezkify.resellers`, replacing those of the same snapshots.
+pub async fn resellers(date: Option<&str>, threshold: f64, dry_run: bool) -> anyhow::Result<()> {
+    use tokio_postgres::types::Type;
+
+    const ITEMS: &str = "with at as (select key, max(\"time\") as \"time\" from ezkify.items where \"time\" < $1::text::date + 1 group by key) \
+        select i.key, i.id, i.\"time\", i.service, i.description, i.rate_per_1k, i.min_order, i.max_order \
+        from ezkify.items as i join at using (key, \"time\") \
+        order by i.key, i.id";
+
+    let mut conn = get_connection().await?;
+    let stmt = conn.prepare_static(ITEMS.into()).await?;
+    let services = conn
+        .query(&stmt, &[&date.unwrap_or("infinity")])
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(Service {
+                key: row.try_get(0)?,
+                id: row.try_get(1)?,
+                time: row.try_get(2)?,
+                name: row.try_get(3)?,
+                description: row.try_get(4)?,
+                rate_per_1k: row.try_get(5)?,
+                min_order: row.try_get(6)?,
+                max_order: row.try_get(7)?,
+            })
+        })
+        .collect::<Result<Vec<_>, tokio_postgres::Error>>()?;
+
+    let mut snapshots = BTreeMap::new();
+    for s in &services {
+        snapshots.entry(&*s.key).or_insert((s.time, 0)).1 += 1;
+    }
+
+    let links = links(&services, threshold);
+    println!("resold services at {}, similarity at least {threshold}", date.unwrap_or("the latest snapshots"));
+    for (key, (time, n)) in &snapshots {
+        println!("    {key}: {n} services, {}", httpdate::fmt_http_date(*time));
+    }
+    println!();
+    for chain in chains(&links) {
+        let total = snapshots.get(chain.downstream).map_or(0, |x| x.1);
+        println!(
+            "\x1b[36m{}\x1b[0m <- \x1b[36m{}\x1b[0m: {} of {total} services, median markup x{:.2}",
+            chain.downstream, chain.upstream, chain.services, chain.markup,
+        );
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let (keys, times) = snapshots.iter().map(|(key, (time, _))| (*key, *time)).unzip::<_, _, Vec<_>, Vec<_>>();
+    let bulk = Bulk::new("ezkify.resellers", &links)
+        .column("key", Type::TEXT, |x| &*x.service.key)
+        .column("id", Type::INT8, |x| x.service.id)
+        .column("time", Type::TIMESTAMP, |x| x.service.time)
+        .column("upstream_key", Type::TEXT, |x| &*x.upstream.key)
+        .column("upstream_id", Type::INT8, |x| x.upstream.id)
+        .column("upstream_time", Type::TIMESTAMP, |x| x.upstream.time)
+        .column("similarity", Type::FLOAT8, |x| x.similarity)
+        .column("markup", Type::FLOAT8, |x| x.markup);
+
+    let txn = conn.transaction().await?;
+    let removed = txn
+        .execute(
+            "delete from ezkify.resellers as r using unnest($1::text[], $2::timestamp[]) as s(key, \"time\") \
+             where r.key = s.key and r.\"time\" = s.\"time\"",
+            &[&keys, &times],
+        )
+        .await?;
+    let stored = if bulk.is_empty() { 0 } else { txn.query(&bulk.sql(), &bulk.params()).await?.len() };
+    txn.commit().await?;
+    tracing::info!(target: "resellers", "ezkify.resellers: \x1b[32m{stored}\x1b[0m links stored, {removed} replaced");
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use core::fmt;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// What a service sells, comparable across panels.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Class {
+    pub platform: &'static str,
+    pub metric: &'static str,
+    /// Sorted.
+    pub flags: Vec<&'static str>,
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.platform, self.metric)?;
+        if !self.flags.is_empty() {
+            write!(f, " [{}]", self.flags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn table(rules: &[(&'static str, &str)]) -> Vec<(&'static str, Regex)> {
+    rules.iter().map(|&(name, re)| (name, Regex::new(&format!(r"(?i)(?:{re})")).unwrap())).collect()
+}
+
+static PLATFORMS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| table(&[
+    ("Instagram", r"instagram|\binsta\b|\big\b"),
+    ("TikTok", r"tik\s?tok|\btt\b"),
+    ("Telegram", r"telegram|\btg\b"),
+    ("YouTube", r"you\s?tube|\byt\b"),
+    ("Facebook", r"facebook|\bfb\b"),
+    ("Twitter", r"twitter|\btweets?\b|\bretweets?\b|\bx\.com\b|\bx\s+(?:followers|likes|views|reposts|impressions)\b"),
+    ("Threads", r"\bthreads\b"),
+    ("Spotify", r"spotify"),
+    ("SoundCloud", r"sound\s?cloud"),
+    ("Twitch", r"twitch"),
+    ("Kick", r"\bkick\b"),
+    ("Discord", r"discord"),
+    ("LinkedIn", r"linked\s?in"),
+    ("Snapchat", r"snapchat"),
+    ("Pinterest", r"pinterest"),
+    ("Reddit", r"reddit"),
+    ("Website", r"website|\btraffic\b"),
+]));
+
+static METRICS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| table(&[
+    ("story views", r"\bstor(?:y|ies)\s+views?\b"),
+    ("live viewers", r"\blive\s*(?:stream)?\s*(?:viewers?|views?)\b"),
+    ("watch time", r"\bwatch\s*(?:time|hours?)\b"),
+    ("followers", r"\bfollowers?\b"),
+    ("subscribers", r"\bsubscribers?\b|\bsubs\b"),
+    ("members", r"\bmembers?\b"),
+    ("likes", r"\blikes?\b|\bhearts?\b"),
+    ("views", r"\bviews?\b"),
+    ("plays", r"\bplays?\b|\bstreams?\b|\blistens?\b"),
+    ("comments", r"\bcomments?\b"),
+    ("shares", r"\bshares?\b|\breposts?\b|\bretweets?\b"),
+    ("saves", r"\bsaves?\b"),
+    ("reactions", r"\breactions?\b"),
+    ("impressions", r"\bimpressions?\b|\breach\b"),
+    ("votes", r"\bvotes?\b|\bpolls?\b"),
+]));
+
+static FLAGS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| table(&[
+    ("real", r"\breal\b|\bhuman\b|\bactive\b"),
+    ("hq", r"\bhq\b|high\s*quality|\bpremium\b"),
+    ("lq", r"\blq\b|low\s*quality"),
+    ("bot", r"\bbots?\b|\bfake\b"),
+    ("non-drop", r"\bnon[\s-]?drop\b|\bno[\s-]?drop\b|\bzero[\s-]?drop\b"),
+    ("targeted", r"targeted|\b(?:usa|uk|india(?:n)?|brazil(?:ian)?|turk(?:ey|ish)|arab(?:ic)?|russia(?:n)?|german(?:y)?|france|french|spain|spanish|ital(?:y|ian)|korea(?:n)?|japan(?:ese)?|indonesia(?:n)?)\b"),
+]));
+
+static NO_REFILL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bno[\s-]?refill\b").unwrap());
+static REFILL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)refill|\bR\d+\b|\d+\s*d(?:ays?)?\s*guarantee").unwrap());
+
+/// The rule of `rules` matching earliest in `text`.
+fn earliest(rules: &[(&'static str, Regex)], text: &str) -> Option<&'static str> {
+    rules
+        .iter()
+        .filter_map(|(name, re)| re.find(text).map(|m| (m.start(), *name)))
+        .min_by_key(|&(start, _)| start)
+        .map(|(_, name)| name)
+}
+
+/// Classifies the service `service` of the category `category`, whose
+/// refill is `refill` if known.
+///
+/// The platform and metric are looked for in the service name first, then
+/// in the category name; [`None`] if either is not found.
+pub fn classify(service: &str, category: &str, refill: Option<bool>) -> Option<Class> {
+    let platform = earliest(&PLATFORMS, service).or_else(|| earliest(&PLATFORMS, category))?;
+    let metric = earliest(&METRICS, service).or_else(|| earliest(&METRICS, category))?;
+
+    let mut flags = FLAGS.iter().filter(|(_, re)| re.is_match(service)).map(|(name, _)| *name).collect::<Vec<_>>();
+    let refill = refill.or_else(|| {
+        if NO_REFILL.is_match(service) {
+            Some(false)
+        } else {
+            REFILL.is_match(service).then_some(true)
+        }
+    });
+    if refill == Some(true) {
+        flags.push("refill");
+    }
+    flags.sort_unstable();
+
+    Some(Class { platform, metric, flags })
+}
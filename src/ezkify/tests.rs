@@ -0,0 +1,395 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    Item, api,
+    attrs::{self, Attributes},
+    money::{self, Fx, Money},
+    panel,
+    report::{Offer, rank},
+    resellers::{Service, chains, links},
+    taxonomy::{Class, classify},
+};
+
+const EZKIFY: &str = include_str!("../../fixtures/ezkify/ezkify.html");
+const DRIPFEEDPANEL: &str = include_str!("../../fixtures/ezkify/dripfeedpanel.html");
+const SMMRAPID: &str = include_str!("../../fixtures/ezkify/smmrapid.html");
+const API: &str = include_str!("../../fixtures/ezkify/api.json");
+
+fn date() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000)
+}
+
+fn fx() -> Fx {
+    Fx::from_json(r#"{"EUR": 2, "RUB": 0.5}"#).unwrap()
+}
+
+fn parse(key: &str, body: &str) -> (Vec<(i64, String)>, Vec<Item>) {
+    let panel = panel::registry(panel::BUILTIN).unwrap().remove(key).unwrap();
+    let (categories, items) = panel.services(&key.into(), &fx(), body, date()).unwrap();
+    assert!(categories.iter().all(|c| c.key == key));
+    assert!(items.iter().all(|i| i.key == key && i.item.time == date()));
+    (
+        categories.into_iter().map(|c| (c.id, c.desc)).collect(),
+        items.into_iter().map(|i| i.item).collect(),
+    )
+}
+
+#[test]
+fn money_formats() {
+    let money = |text: &str| money::parse(text, "USD").map(|m| (m.amount, m.currency.to_string()));
+    let cases: &[(&str, f64, &str)] = &[
+        ("$1.20", 1.2, "USD"),
+        ("  $ 0.5 ", 0.5, "USD"),
+        ("1.20", 1.2, "USD"),
+        ("US$3", 3.0, "USD"),
+        ("0.85 USD", 0.85, "USD"),
+        ("usd 0.85", 0.85, "USD"),
+        // comma decimals
+        ("1,20 €", 1.2, "EUR"),
+        ("€0,500", 0.5, "EUR"),
+        ("12,5 руб.", 12.5, "RUB"),
+        // thousands separators
+        ("₹1,234", 1234.0, "INR"),
+        ("₹ 1 234.5", 1234.5, "INR"),
+        ("1.234,56 ₽", 1234.56, "RUB"),
+        ("1,234.56 ₽", 1234.56, "RUB"),
+        ("R$ 1.000.000", 1_000_000.0, "BRL"),
+        ("1'000.5 EUR", 1000.5, "EUR"),
+    ];
+    for &(text, amount, currency) in cases {
+        assert_eq!(money(text), Some((amount, currency.to_owned())), "{text:?}");
+    }
+    // ambiguous (JPY or CNY)
+    assert_eq!(money("¥1"), None);
+    assert_eq!(money("free"), None);
+    assert_eq!(money::parse("0,9", "RUB"), Some(Money { amount: 0.9, currency: "RUB".into() }));
+}
+
+#[test]
+fn fx_table() {
+    let fx = fx();
+    assert_eq!(fx.usd(&Money { amount: 3.0, currency: "USD".into() }), Some(3.0));
+    assert_eq!(fx.usd(&Money { amount: 3.0, currency: "EUR".into() }), Some(6.0));
+    assert_eq!(fx.usd(&Money { amount: 3.0, currency: "JPY".into() }), None);
+    let builtin = Fx::from_json(money::BUILTIN_FX).unwrap();
+    assert!(builtin.usd(&Money { amount: 1.0, currency: "EUR".into() }).is_some());
+}
+
+#[test]
+fn description_attributes() {
+    let cases: &[(&str, Attributes)] = &[
+        ("Refill: 30 days\nCancel: ✅", Attributes { refill: Some(true), refill_days: Some(30), cancel: Some(true), ..Attributes::default() }),
+        ("♻️ 60 Days Refill\nNon Cancel", Attributes { refill: Some(true), refill_days: Some(60), cancel: Some(false), ..Attributes::default() }),
+        ("Lifetime refill | Drip-feed: ON", Attributes { refill: Some(true), drip_feed: Some(true), ..Attributes::default() }),
+        ("No Refill\nDrip Feed: Disabled\nCancel button enabled", Attributes { refill: Some(false), cancel: Some(true), drip_feed: Some(false), ..Attributes::default() }),
+        ("Guarantee: none", Attributes { refill: Some(false), ..Attributes::default() }),
+        ("Average time: 1 hour 30 minutes", Attributes { average_seconds: Some(5400), ..Attributes::default() }),
+        ("Avg. Time - 2d", Attributes { average_seconds: Some(172_800), ..Attributes::default() }),
+        ("Start: 0-1 hours\nSpeed: 5K/day", Attributes::default()),
+    ];
+    for (description, expected) in cases {
+        assert_eq!(Attributes::from_description(description), *expected, "{description:?}");
+    }
+}
+
+#[test]
+fn attribute_cells() {
+    assert_eq!(attrs::refill("30 Days"), (Some(true), Some(30)));
+    assert_eq!(attrs::refill("Lifetime"), (Some(true), None));
+    assert_eq!(attrs::refill("❌"), (Some(false), None));
+    assert_eq!(attrs::refill("?"), (None, None));
+    assert_eq!(attrs::seconds("Instant"), Some(0));
+    assert_eq!(attrs::seconds("1h 30m"), Some(5400));
+    assert_eq!(attrs::seconds("2,5 hours"), Some(9000));
+    assert_eq!(attrs::seconds("Not enough data"), None);
+}
+
+fn summary(items: &[Item]) -> Vec<(i64, i64, &str, f64, i64, i64)> {
+    items
+        .iter()
+        .map(|i| (i.id, i.cid, &*i.service, i.rate_per_1k, i.min_order, i.max_order))
+        .collect()
+}
+
+#[test]
+fn ezkify() {
+    let (categories, items) = parse("ezkify", EZKIFY);
+    assert_eq!(categories, [(5, "Instagram Followers".to_owned()), (9, "TikTok Views".to_owned())]);
+    // 204 is in a currency without an FX rate and is skipped
+    assert_eq!(summary(&items), [
+        (101, 5, "Instagram Followers [Real]", 0.85, 10, 10_000),
+        // thousands separated by commas
+        (102, 5, "Instagram Followers [Bots]", 0.1, 100, 1_000_000),
+        (201, 9, "TikTok Views", 0.02, 100, 1000),
+        // and by (non-breaking) spaces
+        (202, 9, "TikTok Views [Fast]", 0.02, 1000, 5_000_000),
+        // and by dots and apostrophes
+        (203, 9, "TikTok Views [RU]", 617.25, 1000, 1_000_000),
+    ]);
+    let rates = items.iter().map(|i| (i.rate, &*i.currency)).collect::<Vec<_>>();
+    assert_eq!(rates, [(0.85, "USD"), (0.1, "USD"), (0.01, "EUR"), (0.02, "USD"), (1234.5, "RUB")]);
+    assert_eq!(items[0].description, "Start: 0-1 hours\nSpeed: 5K/day\nRefill: 30 days");
+    assert_eq!(items[0].attrs, Attributes { refill: Some(true), refill_days: Some(30), ..Attributes::default() });
+    // no `.d-none`
+    assert_eq!(items[1].description, "");
+    assert_eq!(items[3].description, "Instant");
+}
+
+#[test]
+fn dripfeedpanel() {
+    let (categories, items) = parse("dripfeedpanel", DRIPFEEDPANEL);
+    assert_eq!(categories, [(3, "YouTube Likes".to_owned())]);
+    assert_eq!(summary(&items), [
+        (3011, 3, "YouTube Likes", 1.2, 50, 20_000),
+        (3012, 3, "YouTube Likes [Slow]", 0.7, 50, 5000),
+    ]);
+    assert_eq!(items[0].description, "No refill\nDrop: none");
+    // from the average time column, and the description
+    assert_eq!(items[0].attrs, Attributes { refill: Some(false), average_seconds: Some(43200), ..Attributes::default() });
+    assert_eq!(items[1].attrs, Attributes { average_seconds: Some(172_800), ..Attributes::default() });
+    // no `.modal-body`
+    assert_eq!(items[1].description, "");
+}
+
+#[test]
+fn smmrapid() {
+    let (categories, items) = parse("smmrapid", SMMRAPID);
+    let cid = uscr::util::stable_id("Twitter Followers");
+    assert_eq!(categories, [(cid, "Twitter Followers".to_owned())]);
+    assert_eq!(summary(&items), [(77, cid, "Twitter Followers [HQ]", 2.5, 100, 50_000)]);
+    assert_eq!(items[0].description, "Start: instant\nGuarantee: none");
+    assert_eq!(items[0].attrs.refill, Some(false));
+}
+
+#[test]
+fn missing_table() {
+    let panel = panel::registry(panel::BUILTIN).unwrap().remove("ezkify").unwrap();
+    assert!(panel.services(&"ezkify".into(), &fx(), "<html><body></body></html>", date()).is_err());
+}
+
+#[test]
+fn layout_detection() {
+    let registry = panel::registry(panel::BUILTIN).unwrap();
+    let detect = |body| panel::detect(&registry, &fx(), body, date()).map(|(layout, _)| layout.as_str());
+    // ezkify, fullsmm, smmcost and n1panel share a layout, the first wins
+    assert_eq!(detect(EZKIFY).unwrap(), "ezkify");
+    assert_eq!(detect(DRIPFEEDPANEL).unwrap(), "dripfeedpanel");
+    assert_eq!(detect(SMMRAPID).unwrap(), "smmrapid");
+
+    // a detected layout gives the same items as the registered one
+    let (_, detected) = panel::detect(&registry, &fx(), SMMRAPID, date()).unwrap();
+    let (_, items) = detected.services(&"somepanel".into(), &fx(), SMMRAPID, date()).unwrap();
+    assert_eq!(items.len(), parse("smmrapid", SMMRAPID).1.len());
+
+    let e = detect("<html><body><table><tr><td>1</td></tr></table></body></html>").unwrap_err().to_string();
+    assert!(e.starts_with("no known layout fits the page (dripfeedpanel: no table; ezkify: no table;"), "{e}");
+}
+
+#[test]
+fn builtin_registry() {
+    let panels = panel::registry(panel::BUILTIN).unwrap();
+    let keys = panels.keys().map(|k| &**k).collect::<Vec<_>>();
+    assert_eq!(keys, ["dripfeedpanel", "ezkify", "fullsmm", "n1panel", "smmcost", "smmrapid"]);
+}
+
+#[test]
+fn bad_registry() {
+    let e = panel::registry(r#"{"x": {"url": "https://x/", "table": ">>"}}"#).err().unwrap();
+    assert!(e.to_string().contains("bad selector"), "{e}");
+}
+
+#[test]
+fn api_services() {
+    let (categories, items) = api::services(&"smmcost".into(), "USD", &fx(), API, date()).unwrap();
+    let followers = uscr::util::stable_id("Instagram Followers");
+    let views = uscr::util::stable_id("TikTok Views");
+    let categories = categories.into_iter().map(|c| (c.id, c.desc)).collect::<Vec<_>>();
+    assert_eq!(categories, [(followers, "Instagram Followers".to_owned()), (views, "TikTok Views".to_owned())]);
+
+    let items = items.into_iter().map(|i| i.item).collect::<Vec<_>>();
+    // numbers as strings or not; 32 (no rate) and 33 (no min) are skipped
+    assert_eq!(summary(&items), [
+        (1, followers, "Instagram Followers [Real]", 0.9, 50, 10_000),
+        (2, followers, "Instagram Followers [Bots]", 0.1, 100, 1_000_000),
+        (30, views, "TikTok Views", 0.012, 100, 5_000_000),
+        (31, views, "TikTok Likes", 1.5, 10, 50_000),
+    ]);
+    let flags = items.iter().map(|i| (i.attrs.refill, i.attrs.cancel, i.attrs.drip_feed)).collect::<Vec<_>>();
+    assert_eq!(flags, [
+        (Some(true), Some(true), None),
+        (Some(false), Some(false), Some(true)),
+        (None, None, None),
+        (Some(true), Some(false), Some(false)),
+    ]);
+    assert!(items.iter().all(|i| i.time == date() && i.description.is_empty()));
+}
+
+#[test]
+fn api_error() {
+    let e = api::services(&"smmcost".into(), "USD", &fx(), r#"{"error": "Invalid API key"}"#, date()).err().unwrap();
+    assert_eq!(e.to_string(), "api error: Invalid API key");
+}
+
+#[test]
+fn api_endpoint() {
+    let panels = panel::registry(panel::BUILTIN).unwrap();
+    assert_eq!(panels["fullsmm"].api().unwrap().as_str(), "https://panel.fullsmm.com/api/v2");
+}
+
+/// Serves [`API`] at `/api/v2` to the key `0123456789abcdef`, an error
+/// otherwise; returns the URL.
+async fn api_server() -> String {
+    use std::collections::HashMap;
+
+    use axum::{Form, Router, routing::post};
+
+    async fn v2(Form(form): Form<HashMap<String, String>>) -> ([(&'static str, &'static str); 1], &'static str) {
+        let body = if form.get("key").map(String::as_str) == Some(API_KEY) && form.get("action").map(String::as_str) == Some("services") {
+            API
+        } else {
+            r#"{"error": "Invalid API key"}"#
+        };
+        ([("content-type", "application/json")], body)
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/api/v2", listener.local_addr().unwrap());
+    tokio::spawn(axum::serve(listener, Router::new().route("/api/v2", post(v2))).into_future());
+    url
+}
+
+const API_KEY: &str = "0123456789abcdef";
+
+#[tokio::test]
+async fn api_mock() {
+    use uscr::scrape::{pipeline::fetch, retry::Retry};
+
+    let url = api_server().await;
+    let client = uscr::scrape::simple();
+    let retry = Retry::new(None);
+    let page = fetch(&retry, (), api::request(&client, &url, API_KEY)).await.unwrap();
+    let (categories, items) = api::services(&"mock".into(), "USD", &fx(), &page.body, page.date).unwrap();
+    assert_eq!((categories.len(), items.len()), (2, 4));
+
+    let page = fetch(&retry, (), api::request(&client, &url, "wrong")).await.unwrap();
+    assert!(api::services(&"mock".into(), "USD", &fx(), &page.body, page.date).is_err());
+}
+
+#[tokio::test]
+async fn api_key_not_archived() {
+    use std::io::Read;
+
+    use uscr::scrape::{
+        archive::{self, Archive},
+        pipeline::fetch,
+        retry::Retry,
+    };
+
+    let dir = std::env::temp_dir().join(format!("uscr-ezkify-api-archive-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let archive = Archive::open(&dir, "mock").unwrap();
+
+    let url = api_server().await;
+    let retry = Retry::new(None).archive(Some(&archive));
+    let page = fetch(&retry, (), api::request(&uscr::scrape::simple(), &url, API_KEY)).await.unwrap();
+    assert_eq!(api::services(&"mock".into(), "USD", &fx(), &page.body, page.date).unwrap().1.len(), 4);
+    drop(archive);
+
+    let entries = archive::entries(&dir).unwrap();
+    assert_eq!(entries.len(), 1);
+    let archived = archive::read(&dir, entries[0].clone()).unwrap();
+    assert_eq!(archived.body, API);
+    assert_eq!(archived.request, b"key=REDACTED&action=services");
+
+    let mut warc = Vec::new();
+    flate2::read::MultiGzDecoder::new(std::fs::File::open(dir.join(&entries[0].file)).unwrap()).read_to_end(&mut warc).unwrap();
+    assert!(!warc.is_empty() && !warc.windows(API_KEY.len()).any(|w| w == API_KEY.as_bytes()));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn class(platform: &'static str, metric: &'static str, flags: &[&'static str]) -> Class {
+    Class { platform, metric, flags: flags.to_vec() }
+}
+
+#[test]
+fn taxonomy() {
+    let cases: &[(&str, &str, Option<bool>, Option<Class>)] = &[
+        ("Instagram Followers [Real] [30D Refill]", "", None, Some(class("Instagram", "followers", &["real", "refill"]))),
+        ("IG Likes + Impressions | Non Drop", "", None, Some(class("Instagram", "likes", &["non-drop"]))),
+        ("Story Views [Fast]", "Instagram", None, Some(class("Instagram", "story views", &[]))),
+        ("TikTok Views", "TikTok", Some(false), Some(class("TikTok", "views", &[]))),
+        ("Telegram Post Views [Last 5 posts]", "", None, Some(class("Telegram", "views", &[]))),
+        ("Members [USA] [No Refill]", "Telegram Channel", None, Some(class("Telegram", "members", &["targeted"]))),
+        ("YouTube Live Stream Views", "", None, Some(class("YouTube", "live viewers", &[]))),
+        ("YT Watch Time Hours [R30]", "", None, Some(class("YouTube", "watch time", &["refill"]))),
+        ("X Followers [Bots]", "", Some(true), Some(class("Twitter", "followers", &["bot", "refill"]))),
+        ("Spotify Plays [HQ]", "", None, Some(class("Spotify", "plays", &["hq"]))),
+        ("Followers", "Misc", None, None),
+        ("Instagram Mentions", "", None, None),
+    ];
+    for (service, category, refill, expected) in cases {
+        assert_eq!(classify(service, category, *refill), *expected, "{service:?} in {category:?}");
+    }
+    assert_eq!(class("Instagram", "followers", &["real", "refill"]).to_string(), "Instagram followers [real, refill]");
+}
+
+#[test]
+fn ranking() {
+    let offer = |key: &str, id, rate_per_1k| Offer { key: key.into(), id, service: String::new(), rate_per_1k, min_order: 10, max_order: 1000 };
+    let followers = class("Instagram", "followers", &[]);
+    let likes = class("Instagram", "likes", &[]);
+    let ranked = rank([
+        (followers.clone(), offer("a", 1, 0.9)),
+        (followers.clone(), offer("a", 2, 0.7)),
+        (followers.clone(), offer("b", 3, 0.8)),
+        (followers.clone(), offer("c", 4, 1.2)),
+        // only one panel
+        (likes.clone(), offer("a", 5, 0.1)),
+        (likes, offer("a", 6, 0.2)),
+    ], 2);
+    assert_eq!(ranked.len(), 1);
+    let (class, panels) = &ranked[0];
+    assert_eq!(*class, followers);
+    let panels = panels.iter().map(|o| (&*o.key, o.id)).collect::<Vec<_>>();
+    // the cheapest offer of each panel
+    assert_eq!(panels, [("a", 2), ("b", 3), ("c", 4)]);
+}
+
+#[test]
+fn reseller_chains() {
+    let service = |key: &str, id, name: &str, description: &str, rate_per_1k, max_order| Service {
+        key: key.into(),
+        id,
+        time: date(),
+        name: name.into(),
+        description: description.into(),
+        rate_per_1k,
+        min_order: 10,
+        max_order,
+    };
+    let description = "Start: 0-1 hour\nSpeed: 10K/day\nRefill: 30 days\nReal accounts with posts";
+    let services = [
+        service("up", 1, "Instagram Followers [Real] [R30]", description, 0.5, 100_000),
+        service("a", 11, "IG Followers | Real | 30 days refill", description, 0.75, 100_000),
+        service("b", 21, "Instagram Followers - Real", &description.replace("Speed: 10K/day", "Speed: 10K / day"), 1.0, 100_000),
+        // same description, other limits
+        service("c", 31, "Instagram Followers [Real] [R30]", description, 0.6, 50_000),
+        // no descriptions (API), same name
+        service("up", 2, "TikTok Views", "", 0.01, 100_000),
+        service("a", 12, "TikTok Views", "", 0.02, 100_000),
+        // not similar
+        service("b", 22, "YouTube Likes", "Start: instant\nNo refill", 0.9, 100_000),
+    ];
+
+    let found = links(&services, 0.8);
+    let summary = found.iter().map(|l| (&*l.service.key, l.service.id, &*l.upstream.key, l.upstream.id, l.markup)).collect::<Vec<_>>();
+    assert_eq!(summary, [("a", 11, "up", 1, 1.5), ("a", 12, "up", 2, 2.0), ("b", 21, "up", 1, 2.0)]);
+
+    let chains = chains(&found).into_iter().map(|c| (c.upstream, c.downstream, c.services, c.markup)).collect::<Vec<_>>();
+    assert_eq!(chains, [("up", "a", 2, 1.75), ("up", "b", 1, 2.0)]);
+
+    // the same panel is not its own reseller
+    let twice = [services[0].clone(), services[0].clone()];
+    assert!(links(&twice, 0.8).is_empty());
+}
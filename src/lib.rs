@@ -2,4 +2,5 @@
 
 pub mod db;
 pub mod scrape;
+pub mod sink;
 pub mod util;
@@ -0,0 +1,40 @@
+#[derive(clap::Parser)]
+struct Args {
+    #[arg(short, long, help = "Only list applied and pending migrations")]
+    list: bool,
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    use clap::Parser;
+    use uscr::db::migrate::{MIGRATIONS, applied, migrate};
+
+    pretty_env_logger::init_timed();
+
+    let args = Args::parse();
+    uscr::db::init_db(args.db.config()?).await?;
+
+    let mut conn = uscr::db::get_connection().await?;
+
+    if args.list {
+        let applied = applied(&conn).await?;
+        for migration in MIGRATIONS {
+            match applied.iter().find(|x| x.0 == migration.version) {
+                Some((_, _, time)) => println!(
+                    "\x1b[32mapplied\x1b[0m {} at {}",
+                    migration.name,
+                    httpdate::fmt_http_date(*time),
+                ),
+                None => println!("\x1b[33mpending\x1b[0m {}", migration.name),
+            }
+        }
+        return Ok(());
+    }
+
+    let done = migrate(&mut conn).await?;
+    tracing::info!(target: "migrate", "{} migration(s) applied, schema is at version {}", done.len(), MIGRATIONS.last().map_or(0, |m| m.version));
+
+    Ok(())
+}
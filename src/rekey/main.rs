@@ -0,0 +1,186 @@
+//! Recomputes the ids derived from scraped text with [`uscr::util::stable_id`].
+//!
+//! These ids used to be `DefaultHasher` hashes, which may change with the Rust
+//! release. Every id is computed again from the text it was derived from, so
+//! running this more than once is harmless.
+
+#[cfg(test)]
+mod tests;
+
+#[derive(clap::Parser)]
+struct Args {
+    #[arg(short = 'n', long, help = "Show what would change, then roll back")]
+    dry_run: bool,
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+}
+
+/// `(text, id)` for every distinct text of `sql`'s only column.
+async fn keys(txn: &tokio_postgres::Transaction<'_>, sql: &str) -> anyhow::Result<(Vec<String>, Vec<i64>)> {
+    let texts = txn
+        .query(sql, &[])
+        .await?
+        .into_iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<Vec<String>, _>>()?;
+    let ids = texts.iter().map(|text| uscr::util::stable_id(text)).collect();
+    Ok((texts, ids))
+}
+
+/// accs: listing ids are hashes of the description.
+///
+/// A listing may already be stored under its new id too, if the scraper ran
+/// since the upgrade: its events are merged, and so are the two listings (and
+/// their details), the state being taken from the one seen last.
+async fn accs(txn: &tokio_postgres::Transaction<'_>) -> anyhow::Result<()> {
+    let (texts, ids) = keys(txn, "select distinct description from accs.listing").await?;
+    let events = txn
+        .execute(
+            "insert into accs.listing_event \
+             select k.id, e.\"time\", e.kind, e.price, e.quantity, e.old_price, e.old_quantity \
+             from accs.listing_event as e, accs.listing as l, unnest($1::text[], $2::int8[]) as k(description, id) \
+             where e.id = l.id and l.description = k.description and l.id <> k.id on conflict do nothing",
+            &[&texts, &ids],
+        )
+        .await?;
+    txn.execute(
+        "delete from accs.listing_event as e using accs.listing as l, unnest($1::text[], $2::int8[]) as k(description, id) \
+         where e.id = l.id and l.description = k.description and l.id <> k.id",
+        &[&texts, &ids],
+    )
+    .await?;
+
+    // the events were carried over above, the trigger would record the merge
+    // as new ones
+    txn.batch_execute("alter table accs.listing disable trigger listing_event").await?;
+    let listings = txn
+        .execute(
+            "insert into accs.listing as l (id, category, description, quantity, price, first_seen, last_seen, gone) \
+             select distinct on (k.id) k.id, o.category, o.description, o.quantity, o.price, \
+                 min(o.first_seen) over w, max(o.last_seen) over w, o.gone \
+             from accs.listing as o join unnest($1::text[], $2::int8[]) as k(description, id) on o.description = k.description \
+             where o.id <> k.id \
+             window w as (partition by k.id) \
+             order by k.id, o.last_seen desc \
+             on conflict (id) do update set \
+                 category = case when excluded.last_seen > l.last_seen then excluded.category else l.category end, \
+                 quantity = case when excluded.last_seen > l.last_seen then excluded.quantity else l.quantity end, \
+                 price = case when excluded.last_seen > l.last_seen then excluded.price else l.price end, \
+                 gone = case when excluded.last_seen > l.last_seen then excluded.gone else l.gone end, \
+                 first_seen = least(l.first_seen, excluded.first_seen), \
+                 last_seen = greatest(l.last_seen, excluded.last_seen)",
+            &[&texts, &ids],
+        )
+        .await?;
+    // the details refer to the listings, so they move before the old ones go
+    let details = txn
+        .execute(
+            "insert into accs.listing_detail as d (id, item_id, url, seller, warranty, format, \"time\") \
+             select distinct on (k.id) k.id, o.item_id, o.url, o.seller, o.warranty, o.format, o.\"time\" \
+             from accs.listing_detail as o, accs.listing as l, unnest($1::text[], $2::int8[]) as k(description, id) \
+             where o.id = l.id and l.description = k.description and l.id <> k.id \
+             order by k.id, o.\"time\" desc \
+             on conflict (id) do update set \
+                 item_id = excluded.item_id, url = excluded.url, seller = excluded.seller, \
+                 warranty = excluded.warranty, format = excluded.format, \"time\" = excluded.\"time\" \
+             where excluded.\"time\" > d.\"time\"",
+            &[&texts, &ids],
+        )
+        .await?;
+    txn.execute(
+        "delete from accs.listing_detail as d using accs.listing as l, unnest($1::text[], $2::int8[]) as k(description, id) \
+         where d.id = l.id and l.description = k.description and l.id <> k.id",
+        &[&texts, &ids],
+    )
+    .await?;
+    txn.execute(
+        "delete from accs.listing as l using unnest($1::text[], $2::int8[]) as k(description, id) \
+         where l.description = k.description and l.id <> k.id",
+        &[&texts, &ids],
+    )
+    .await?;
+    txn.batch_execute("alter table accs.listing enable trigger listing_event").await?;
+    tracing::info!(
+        target: "rekey",
+        "accs.listing: \x1b[32m{listings}\x1b[0m rows, accs.listing_event: \x1b[32m{events}\x1b[0m rows, accs.listing_detail: \x1b[32m{details}\x1b[0m rows",
+    );
+
+    let (texts, ids) = keys(txn, "select distinct description from accs.market").await?;
+    let market = txn
+        .execute(
+            "insert into accs.market (id, category, \"time\", description, quantity, price) \
+             select k.id, m.category, m.\"time\", m.description, m.quantity, m.price \
+             from accs.market as m join unnest($1::text[], $2::int8[]) as k(description, id) on m.description = k.description \
+             where m.id <> k.id on conflict do nothing",
+            &[&texts, &ids],
+        )
+        .await?;
+    txn.execute(
+        "delete from accs.market as m using unnest($1::text[], $2::int8[]) as k(description, id) \
+         where m.description = k.description and m.id <> k.id",
+        &[&texts, &ids],
+    )
+    .await?;
+    tracing::info!(target: "rekey", "accs.market: \x1b[32m{market}\x1b[0m rows");
+    Ok(())
+}
+
+/// ezkify: smmrapid category ids are hashes of the category text; items
+/// refer to them, so the new categories are added before the old ones go.
+async fn ezkify(txn: &tokio_postgres::Transaction<'_>) -> anyhow::Result<()> {
+    let (texts, ids) = keys(txn, "select distinct \"desc\" from ezkify.categories where key = 'smmrapid'").await?;
+    let categories = txn
+        .execute(
+            "insert into ezkify.categories (key, id, \"desc\") \
+             select c.key, k.id, c.\"desc\" from ezkify.categories as c join unnest($1::text[], $2::int8[]) as k(\"desc\", id) using (\"desc\") \
+             where c.key = 'smmrapid' and c.id <> k.id on conflict do nothing",
+            &[&texts, &ids],
+        )
+        .await?;
+    let items = txn
+        .execute(
+            "update ezkify.items as i set category_id = k.id \
+             from ezkify.categories as c, unnest($1::text[], $2::int8[]) as k(\"desc\", id) \
+             where i.key = 'smmrapid' and c.key = 'smmrapid' and i.category_id = c.id and c.\"desc\" = k.\"desc\" and c.id <> k.id",
+            &[&texts, &ids],
+        )
+        .await?;
+    txn.execute(
+        "delete from ezkify.categories as c using unnest($1::text[], $2::int8[]) as k(\"desc\", id) \
+         where c.key = 'smmrapid' and c.\"desc\" = k.\"desc\" and c.id <> k.id",
+        &[&texts, &ids],
+    )
+    .await?;
+    tracing::info!(target: "rekey", "ezkify.categories: \x1b[32m{categories}\x1b[0m rows, ezkify.items: \x1b[32m{items}\x1b[0m rows");
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    use clap::Parser;
+    use uscr::db::migrate::{MIGRATIONS, applied};
+
+    pretty_env_logger::init_timed();
+
+    let args = Args::parse();
+    uscr::db::init_db(args.db.config()?).await?;
+
+    let mut conn = uscr::db::get_connection().await?;
+    let applied = applied(&conn).await?;
+    if let Some(pending) = MIGRATIONS.iter().find(|m| !applied.iter().any(|x| x.0 == m.version)) {
+        anyhow::bail!("migration {} is pending, run ./migrate first", pending.name);
+    }
+
+    let txn = conn.transaction().await?;
+    accs(&txn).await?;
+    ezkify(&txn).await?;
+
+    if args.dry_run {
+        txn.rollback().await?;
+        tracing::info!(target: "rekey", "dry run, rolled back");
+    } else {
+        txn.commit().await?;
+    }
+
+    Ok(())
+}
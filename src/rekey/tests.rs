@@ -0,0 +1,105 @@
+//! These run against the database configured as for `rekey` itself, inside a
+//! transaction that is rolled back: `cargo test --bin rekey -- --ignored`.
+
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated database"]
+async fn listing_already_rekeyed() {
+    const LISTING: &str = "insert into accs.listing (id, category, description, quantity, price, first_seen, last_seen) \
+        values ($1, 7, $2, $3, $4, $5, $6)";
+    const MARKET: &str = "insert into accs.market (id, category, \"time\", description, quantity, price) values ($1, 7, $2, $3, 1, 1)";
+    const DETAIL: &str = "insert into accs.listing_detail (id, url, seller, \"time\") values ($1, $2, $2, $3)";
+
+    uscr::db::init_db(crate::Args::parse_from(["rekey"]).db.config().unwrap()).await.unwrap();
+    let mut conn = uscr::db::get_connection().await.unwrap();
+    let txn = conn.transaction().await.unwrap();
+
+    // stored under the old id, then scraped again under the new one
+    let newer = "rekey test: seen last under the new id";
+    let (old, new) = (-42_i64, uscr::util::stable_id(newer));
+    txn.execute(LISTING, &[&old, &newer, &10_i64, &1.5, &at(0), &at(100)]).await.unwrap();
+    txn.execute("update accs.listing set price = 2, last_seen = $2 where id = $1", &[&old, &at(200)]).await.unwrap();
+    txn.execute(LISTING, &[&new, &newer, &8_i64, &2.5, &at(300), &at(400)]).await.unwrap();
+    for (id, time) in [(old, at(0)), (old, at(200)), (new, at(300))] {
+        txn.execute(MARKET, &[&id, &time, &newer]).await.unwrap();
+    }
+
+    // the other way round
+    let older = "rekey test: seen last under the old id";
+    let (old2, new2) = (-43_i64, uscr::util::stable_id(older));
+    txn.execute(LISTING, &[&new2, &older, &6_i64, &4.0, &at(600), &at(700)]).await.unwrap();
+    txn.execute(LISTING, &[&old2, &older, &5_i64, &3.0, &at(500), &at(900)]).await.unwrap();
+
+    // the details of both, the one fetched last wins; or of the old id only
+    txn.execute(DETAIL, &[&old, &"old", &at(150)]).await.unwrap();
+    txn.execute(DETAIL, &[&new, &"new", &at(350)]).await.unwrap();
+    txn.execute(DETAIL, &[&old2, &"moved", &at(800)]).await.unwrap();
+
+    crate::accs(&txn).await.unwrap();
+
+    let listing = |description: &'static str| {
+        let txn = &txn;
+        async move {
+            let rows = txn
+                .query("select id, quantity, price, first_seen, last_seen from accs.listing where description = $1", &[&description])
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 1);
+            let row = &rows[0];
+            (
+                row.get::<_, i64>(0),
+                row.get::<_, i64>(1),
+                row.get::<_, f64>(2),
+                row.get::<_, SystemTime>(3),
+                row.get::<_, SystemTime>(4),
+            )
+        }
+    };
+    assert_eq!(listing(newer).await, (new, 8, 2.5, at(0), at(400)));
+    assert_eq!(listing(older).await, (new2, 5, 3.0, at(500), at(900)));
+
+    let details = txn
+        .query("select id, seller, \"time\" from accs.listing_detail where id = any($1) order by 3", &[&[old, new, old2, new2].as_slice()])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get::<_, i64>(0), row.get::<_, String>(1), row.get::<_, SystemTime>(2)))
+        .collect::<Vec<_>>();
+    assert_eq!(details, [(new, "new".to_owned(), at(350)), (new2, "moved".to_owned(), at(800))]);
+
+    let events = txn
+        .query("select id, \"time\", kind from accs.listing_event where id = any($1) order by 1, 2, 3", &[&[old, new, old2, new2].as_slice()])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get::<_, i64>(0), row.get::<_, SystemTime>(1), row.get::<_, String>(2)))
+        .collect::<Vec<_>>();
+    let mut expected = [
+        (new, at(0), "appeared"),
+        (new, at(200), "price"),
+        (new, at(300), "appeared"),
+        (new2, at(500), "appeared"),
+        (new2, at(600), "appeared"),
+    ]
+    .map(|(id, time, kind)| (id, time, kind.to_owned()));
+    expected.sort();
+    assert_eq!(events, expected);
+
+    let market = txn
+        .query("select id, \"time\" from accs.market where description = $1 order by 2", &[&newer])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get::<_, i64>(0), row.get::<_, SystemTime>(1)))
+        .collect::<Vec<_>>();
+    assert_eq!(market, [(new, at(0)), (new, at(200)), (new, at(300))]);
+
+    txn.rollback().await.unwrap();
+}
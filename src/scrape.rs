@@ -1,4 +1,8 @@
+pub mod archive;
+pub mod limit;
+pub mod pipeline;
 pub mod puppeteer;
+pub mod retry;
 
 pub use puppeteer::puppeteer;
 
@@ -0,0 +1,529 @@
+use core::fmt::Write as _;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use parking_lot::Mutex;
+use reqwest::{
+    Request, StatusCode, Version,
+    header::{
+        AUTHORIZATION, CONTENT_TYPE, COOKIE, HeaderMap, HeaderName, HeaderValue, PROXY_AUTHORIZATION, SET_COOKIE,
+        TRANSFER_ENCODING,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::pipeline::Source;
+use crate::util::iso8601;
+
+/// Form fields and query parameters holding credentials (e.g. the API key of
+/// a reseller panel), whose values are not archived.
+const SECRETS: &[&str] = &["key", "api_key", "token", "password"];
+
+/// Headers holding credentials or sessions (e.g. the `cf_clearance` cookie
+/// given to the forum worker), whose values are not archived.
+const SECRET_HEADERS: [HeaderName; 4] = [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE];
+
+/// The request line and headers of a request, kept until its response is archived.
+#[derive(Debug)]
+pub struct Sent {
+    head: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl Sent {
+    #[must_use]
+    pub fn of(request: &Request) -> Self {
+        let url = request.url();
+        let mut target = url.path().to_owned();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(&String::from_utf8_lossy(&redact(query.as_bytes())));
+        }
+        let mut head = format!("{} {target} HTTP/1.1\r\nHost: {}\r\n", request.method(), url.host_str().unwrap_or_default()).into_bytes();
+        write_headers(&mut head, request.headers());
+        let body = request.body().and_then(reqwest::Body::as_bytes).unwrap_or_default();
+        let form = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|v| v.as_bytes().starts_with(b"application/x-www-form-urlencoded"));
+        let body = if form { redact(body) } else { body.to_vec() };
+        Self { head, body }
+    }
+}
+
+/// Replaces the values of the [`SECRETS`] fields of a form or query string.
+fn redact(form: &[u8]) -> Vec<u8> {
+    form.split(|&b| b == b'&')
+        .map(|field| match field.iter().position(|&b| b == b'=') {
+            Some(eq) if SECRETS.iter().any(|name| name.as_bytes() == &field[..eq]) => [&field[..=eq], b"REDACTED"].concat(),
+            _ => field.to_vec(),
+        })
+        .collect::<Vec<_>>()
+        .join(&b'&')
+}
+
+/// `url` with the [`SECRETS`] of its query string redacted.
+fn redact_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!("{base}?{}", String::from_utf8_lossy(&redact(query.as_bytes()))),
+        None => url.to_owned(),
+    }
+}
+
+fn write_headers(out: &mut Vec<u8>, headers: &HeaderMap) {
+    // the body is stored decoded
+    for (name, value) in headers.iter().filter(|(name, _)| *name != TRANSFER_ENCODING) {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(if SECRET_HEADERS.contains(name) { b"REDACTED" } else { value.as_bytes() });
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// An HTTP response, with its request in the preceding record.
+    Response,
+    /// A document captured from a browser (no HTTP exchange).
+    Resource,
+}
+
+/// One line of `index.jsonl`, locating a record in the `.warc.gz` files.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// Unix seconds, from the server `Date` when there is one.
+    pub date: u64,
+    pub kind: Kind,
+    pub status: Option<u16>,
+    pub file: String,
+    /// Byte range of the gzip member holding the record.
+    pub offset: u64,
+    pub length: u64,
+    /// Offset of the request record, which ends where the response starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<u64>,
+}
+
+impl Entry {
+    /// Whether the record is in a file written by an [`Archive`] opened with
+    /// `prefix`, and not by one whose prefix merely starts with it.
+    #[must_use]
+    pub fn archived_by(&self, prefix: &str) -> bool {
+        self.file
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('-')?.strip_suffix(".warc.gz"))
+            .is_some_and(|secs| !secs.is_empty() && secs.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+struct Inner {
+    file: File,
+    offset: u64,
+    index: File,
+}
+
+/// Writes fetched pages as WARC 1.1 records into `<dir>/<prefix>-<unix time>.warc.gz`,
+/// one gzip member per record, and appends an [`Entry`] per page to
+/// `<dir>/index.jsonl`.
+pub struct Archive {
+    dir: PathBuf,
+    name: String,
+    inner: Mutex<Inner>,
+}
+
+fn record_id() -> String {
+    let bits = rand::random::<u128>() & !(0xf << 76 | 0x3 << 62) | (0x4 << 76 | 0x2 << 62);
+    let hex = format!("{bits:032x}");
+    format!("<urn:uuid:{}-{}-{}-{}-{}>", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+impl Archive {
+    pub fn open(dir: &Path, prefix: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let secs = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let name = format!("{prefix}-{secs}.warc.gz");
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(&name))?;
+        let offset = file.metadata()?.len();
+        let index = OpenOptions::new().create(true).append(true).open(dir.join("index.jsonl"))?;
+        Ok(Self { dir: dir.to_owned(), name, inner: Mutex::new(Inner { file, offset, index }) })
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn record(fields: &[(&str, &str)], block: &[&[u8]]) -> io::Result<Vec<u8>> {
+        let mut head = String::from("WARC/1.1\r\n");
+        for (name, value) in fields {
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(value);
+            head.push_str("\r\n");
+        }
+        let len = block.iter().map(|b| b.len()).sum::<usize>();
+        let _ = write!(head, "Content-Length: {len}\r\n\r\n");
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(head.as_bytes())?;
+        for part in block {
+            gz.write_all(part)?;
+        }
+        gz.write_all(b"\r\n\r\n")?;
+        gz.finish()
+    }
+
+    /// Appends `[request,] record` and indexes `record`.
+    fn append(&self, records: &[Vec<u8>], mut entry: Entry) -> io::Result<()> {
+        let mut inner = self.inner.lock();
+        let start = inner.offset;
+        for record in records {
+            inner.file.write_all(record)?;
+            inner.offset += record.len() as u64;
+        }
+        entry.length = records.last().map_or(0, Vec::len) as u64;
+        entry.offset = inner.offset - entry.length;
+        entry.request = (records.len() == 2).then_some(start);
+        entry.file.clone_from(&self.name);
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+        inner.index.write_all(&line)?;
+        drop(inner);
+        Ok(())
+    }
+
+    /// Archives a request and its response.
+    #[allow(clippy::too_many_arguments)]
+    pub fn exchange(
+        &self,
+        url: &str,
+        sent: &Sent,
+        status: StatusCode,
+        version: Version,
+        headers: &HeaderMap,
+        body: &[u8],
+        date: SystemTime,
+    ) -> io::Result<()> {
+        let url = &*redact_url(url);
+        let warc_date = iso8601(date);
+        let response_id = record_id();
+
+        let mut head = format!("{version:?} {status}\r\n").into_bytes();
+        write_headers(&mut head, headers);
+        let response = Self::record(&[
+            ("WARC-Type", "response"),
+            ("WARC-Record-ID", &response_id),
+            ("WARC-Date", &warc_date),
+            ("WARC-Target-URI", url),
+            ("Content-Type", "application/http;msgtype=response"),
+        ], &[&head, body])?;
+        let request = Self::record(&[
+            ("WARC-Type", "request"),
+            ("WARC-Record-ID", &record_id()),
+            ("WARC-Date", &warc_date),
+            ("WARC-Target-URI", url),
+            ("WARC-Concurrent-To", &response_id),
+            ("Content-Type", "application/http;msgtype=request"),
+        ], &[&sent.head, &sent.body])?;
+
+        self.append(&[request, response], Entry {
+            url: url.to_owned(),
+            date: date.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            kind: Kind::Response,
+            status: Some(status.as_u16()),
+            file: String::new(),
+            offset: 0,
+            length: 0,
+            request: None,
+        })
+    }
+
+    /// Archives HTML taken from a browser tab (e.g. [`super::puppeteer::outer_html`]).
+    pub fn resource(&self, url: &str, html: &str, date: SystemTime) -> io::Result<()> {
+        let record = Self::record(&[
+            ("WARC-Type", "resource"),
+            ("WARC-Record-ID", &record_id()),
+            ("WARC-Date", &iso8601(date)),
+            ("WARC-Target-URI", url),
+            ("Content-Type", "text/html; charset=utf-8"),
+        ], &[html.as_bytes()])?;
+
+        self.append(&[record], Entry {
+            url: url.to_owned(),
+            date: date.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            kind: Kind::Resource,
+            status: None,
+            file: String::new(),
+            offset: 0,
+            length: 0,
+            request: None,
+        })
+    }
+}
+
+/// A page read back from an archive.
+#[derive(Debug)]
+pub struct Archived {
+    pub entry: Entry,
+    /// The body of the request (e.g. a posted form), empty for resources.
+    pub request: Vec<u8>,
+    /// The response headers, empty for resources.
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Archived {
+    #[must_use]
+    pub fn date(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.entry.date)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The block of the record at `offset`, without the WARC header.
+fn read_block(file: &mut File, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut record = Vec::new();
+    GzDecoder::new(file.take(length)).read_to_end(&mut record)?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed WARC record");
+    let end = find(&record, b"\r\n\r\n").ok_or_else(invalid)?;
+    let len = record[..end]
+        .split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"Content-Length: "))
+        .and_then(|len| str::from_utf8(len.trim_ascii()).ok()?.parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+    record.get(end + 4..end + 4 + len).map(<[u8]>::to_vec).ok_or_else(invalid)
+}
+
+/// Splits an HTTP message into its headers and body.
+fn split_http(block: &[u8]) -> (HeaderMap, &[u8]) {
+    let end = find(block, b"\r\n\r\n").unwrap_or(block.len());
+    let headers = block[..end]
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .filter_map(|line| {
+            let colon = line.iter().position(|&b| b == b':')?;
+            let name = HeaderName::from_bytes(&line[..colon]).ok()?;
+            let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).ok()?;
+            Some((name, value))
+        })
+        .collect();
+    (headers, block.get(end + 4..).unwrap_or_default())
+}
+
+/// The index of the archive in `dir`, in the order pages were fetched.
+pub fn entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    BufReader::new(File::open(dir.join("index.jsonl"))?)
+        .lines()
+        .map(|line| serde_json::from_str(&line?).map_err(io::Error::other))
+        .collect()
+}
+
+/// Reads the page of `entry` from the archive in `dir`.
+pub fn read(dir: &Path, entry: Entry) -> io::Result<Archived> {
+    let mut file = File::open(dir.join(&entry.file))?;
+    let block = read_block(&mut file, entry.offset, entry.length)?;
+    let (headers, body) = match entry.kind {
+        Kind::Response => split_http(&block),
+        Kind::Resource => (HeaderMap::new(), &*block),
+    };
+    let body = String::from_utf8_lossy(body).into_owned();
+    let request = match entry.request {
+        Some(offset) => split_http(&read_block(&mut file, offset, entry.offset - offset)?).1.to_vec(),
+        None => Vec::new(),
+    };
+    Ok(Archived { entry, request, headers, body })
+}
+
+/// Archived pages as a [`Source`], for parsing again without network.
+///
+/// `map` turns each page into the parser's input, or `None` to skip it.
+pub struct Replay<F> {
+    dir: PathBuf,
+    entries: std::vec::IntoIter<Entry>,
+    map: F,
+}
+
+impl<F> Replay<F> {
+    pub fn new(dir: &Path, entries: Vec<Entry>, map: F) -> Self {
+        Self { dir: dir.to_owned(), entries: entries.into_iter(), map }
+    }
+}
+
+impl<T, F> Source for Replay<F>
+where
+    T: Send + Sync,
+    F: FnMut(Archived) -> Option<anyhow::Result<T>> + Send,
+{
+    type Page = T;
+
+    async fn next(&mut self) -> Option<anyhow::Result<T>> {
+        loop {
+            let entry = self.entries.next()?;
+            let url = entry.url.clone();
+            match read(&self.dir, entry) {
+                Ok(archived) => if let Some(page) = (self.map)(archived) {
+                    return Some(page);
+                },
+                Err(e) => return Some(Err(anyhow::Error::from(e).context(url))),
+            }
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ArchiveArgs {
+    #[arg(
+        long,
+        global = true,
+        env = "USCR_ARCHIVE",
+        value_name = "dir",
+        help = "Also keep every fetched page as WARC in this directory"
+    )]
+    pub archive: Option<PathBuf>,
+}
+
+impl ArchiveArgs {
+    pub fn open(&self, prefix: &str) -> io::Result<Option<Archive>> {
+        self.archive.as_deref().map(|dir| Archive::open(dir, prefix)).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uscr-archive-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = dir("round-trip");
+        let archive = Archive::open(&dir, "test").unwrap();
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+
+        let request = reqwest::Client::new()
+            .post("https://example.com/search?q=1")
+            .body("page=2&sort=new")
+            .build()
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        let body = "<html>\r\n\r\n<p>results</p></html>";
+        archive
+            .exchange("https://example.com/search?q=1", &Sent::of(&request), StatusCode::CREATED, Version::HTTP_11, &headers, body.as_bytes(), date)
+            .unwrap();
+        archive.resource("https://example.com/tab", "<p>rendered</p>", date + Duration::from_secs(5)).unwrap();
+        drop(archive);
+
+        let entries = entries(&dir).unwrap();
+        assert_eq!(entries.len(), 2);
+        let [exchange, resource] = [&entries[0], &entries[1]];
+        assert_eq!((exchange.kind, exchange.status, exchange.date), (Kind::Response, Some(201), 1_750_000_000));
+        assert_eq!((resource.kind, resource.status, resource.date), (Kind::Resource, None, 1_750_000_005));
+        assert_eq!(exchange.request, Some(0));
+        assert_eq!(resource.offset, exchange.offset + exchange.length);
+        assert!(exchange.file.starts_with("test-") && exchange.file == resource.file);
+
+        let page = read(&dir, entries[0].clone()).unwrap();
+        assert_eq!(page.entry.url, "https://example.com/search?q=1");
+        assert_eq!(page.body, body);
+        assert_eq!(page.request, b"page=2&sort=new");
+        assert_eq!(page.headers[CONTENT_TYPE], "text/html");
+        assert!(!page.headers.contains_key(TRANSFER_ENCODING));
+        assert_eq!(page.date(), date);
+
+        let page = read(&dir, entries[1].clone()).unwrap();
+        assert_eq!(page.entry.url, "https://example.com/tab");
+        assert_eq!(page.body, "<p>rendered</p>");
+        assert!(page.request.is_empty() && page.headers.is_empty());
+        assert_eq!(page.date(), date + Duration::from_secs(5));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn redact_form() {
+        let request = reqwest::Client::new()
+            .post("https://example.com/api/v2")
+            .form(&[("key", "s3cret"), ("action", "services"), ("monkey", "1"), ("password", "hunter2")])
+            .build()
+            .unwrap();
+        assert_eq!(Sent::of(&request).body, b"key=REDACTED&action=services&monkey=1&password=REDACTED");
+
+        // only forms are rewritten
+        let request = reqwest::Client::new().post("https://example.com/").body("key=value").build().unwrap();
+        assert_eq!(Sent::of(&request).body, b"key=value");
+    }
+
+    #[test]
+    fn archived_by() {
+        let entry = |file: &str| Entry {
+            url: String::new(),
+            date: 0,
+            kind: Kind::Resource,
+            status: None,
+            file: file.to_owned(),
+            offset: 0,
+            length: 0,
+            request: None,
+        };
+        assert!(entry("smm-1750000000.warc.gz").archived_by("smm"));
+        assert!(entry("smm-fast-1750000000.warc.gz").archived_by("smm-fast"));
+        assert!(!entry("smm-fast-1750000000.warc.gz").archived_by("smm"));
+        assert!(!entry("smm-1750000000.warc.gz").archived_by("sm"));
+        assert!(!entry("smm-.warc.gz").archived_by("smm"));
+        assert!(!entry("smm-1750000000.warc").archived_by("smm"));
+    }
+
+    #[test]
+    fn redact_request() {
+        let dir = dir("redact-request");
+        let archive = Archive::open(&dir, "test").unwrap();
+        let url = "https://example.com/threads/1?token=s3cret&page=2";
+        let request = reqwest::Client::new()
+            .get(url)
+            .header(COOKIE, "cf_clearance=abc; xf_session=def")
+            .header(AUTHORIZATION, "Bearer s3cret")
+            .header("user-agent", "uscr")
+            .build()
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(SET_COOKIE, HeaderValue::from_static("xf_session=ghi; path=/"));
+        let date = SystemTime::UNIX_EPOCH;
+        archive.exchange(url, &Sent::of(&request), StatusCode::OK, Version::HTTP_11, &headers, b"<html>", date).unwrap();
+        drop(archive);
+
+        let entries = entries(&dir).unwrap();
+        assert_eq!(entries[0].url, "https://example.com/threads/1?token=REDACTED&page=2");
+        let mut file = File::open(dir.join(&entries[0].file)).unwrap();
+        let request = read_block(&mut file, 0, entries[0].offset).unwrap();
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with("GET /threads/1?token=REDACTED&page=2 HTTP/1.1\r\n"), "{request}");
+        assert!(request.contains("\r\ncookie: REDACTED\r\n") && request.contains("\r\nauthorization: REDACTED\r\n"));
+        assert!(request.contains("\r\nuser-agent: uscr\r\n"));
+        assert!(!request.contains("s3cret") && !request.contains("abc"));
+
+        let page = read(&dir, entries[0].clone()).unwrap();
+        assert_eq!(page.headers[SET_COOKIE], "REDACTED");
+        assert_eq!(page.body, "<html>");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
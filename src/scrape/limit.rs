@@ -0,0 +1,368 @@
+use core::{str::FromStr, time::Duration};
+use std::{sync::Arc, time::SystemTime};
+
+use compact_str::CompactString;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use reqwest::{
+    Response, StatusCode, Url,
+    header::{HeaderMap, RETRY_AFTER},
+};
+use tokio::{
+    sync::Mutex as AsyncMutex,
+    time::{Instant, sleep, sleep_until},
+};
+
+const BACKOFF_MIN: Duration = Duration::from_secs(4);
+const BACKOFF_MAX: Duration = Duration::from_mins(5);
+
+/// How often requests under one key may start.
+#[derive(Clone, Copy, Debug)]
+pub enum Policy {
+    /// A pause between consecutive requests, drawn from `min..max`.
+    Delay { min: Duration, max: Duration },
+    /// At most `rate` requests per second on average, `burst` at once.
+    Bucket { rate: f64, burst: f64 },
+}
+
+impl Policy {
+    pub const NONE: Self = Self::delay(Duration::ZERO);
+
+    #[must_use]
+    pub const fn delay(delay: Duration) -> Self {
+        Self::Delay { min: delay, max: delay }
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (n, unit) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 1e-3)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else {
+        return Err(format!("missing unit (ms or s) in {s:?}"));
+    };
+    let n = n.parse::<f64>().map_err(|e| format!("{s:?}: {e}"))?;
+    Duration::try_from_secs_f64(n * unit).map_err(|e| format!("{s:?}: {e}"))
+}
+
+/// `none`, `delay:<d>`, `delay:<d>..<d>`, `bucket:<n>/s` or `bucket:<n>/s:<burst>`,
+/// durations being written like `250ms` or `2s`.
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            Ok(Self::NONE)
+        } else if let Some(delay) = s.strip_prefix("delay:") {
+            Ok(if let Some((min, max)) = delay.split_once("..") {
+                let (min, max) = (parse_duration(min)?, parse_duration(max)?);
+                if min > max {
+                    return Err(format!("empty range {delay:?}"));
+                }
+                Self::Delay { min, max }
+            } else {
+                Self::delay(parse_duration(delay)?)
+            })
+        } else if let Some(bucket) = s.strip_prefix("bucket:") {
+            let (rate, burst) = bucket.split_once(':').unwrap_or((bucket, "1"));
+            let rate = rate
+                .strip_suffix("/s")
+                .and_then(|r| r.parse::<f64>().ok())
+                .filter(|r| *r > 0.0)
+                .ok_or_else(|| format!("bad rate {rate:?}, expected e.g. 2/s"))?;
+            let burst = burst
+                .parse::<u32>()
+                .ok()
+                .filter(|b| *b > 0)
+                .ok_or_else(|| format!("bad burst {burst:?}"))?;
+            Ok(Self::Bucket { rate, burst: burst.into() })
+        } else {
+            Err(format!("unknown policy {s:?}, expected none, delay:... or bucket:..."))
+        }
+    }
+}
+
+/// `<key>=<policy>`.
+#[derive(Clone, Debug)]
+pub struct Rate {
+    pub key: CompactString,
+    pub policy: Policy,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, policy) = s.split_once('=').ok_or_else(|| format!("expected <key>=<policy>, got {s:?}"))?;
+        Ok(Self { key: key.into(), policy: policy.parse()? })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RateArgs {
+    #[arg(
+        long = "rate",
+        global = true,
+        value_name = "key=policy",
+        help = "Pacing of a host or account, e.g. accsmarket.com=delay:250ms or *=bucket:2/s:4 (repeatable)"
+    )]
+    pub rates: Vec<Rate>,
+}
+
+struct State {
+    policy: Policy,
+    next: Option<Instant>,
+    tokens: f64,
+    refilled: Instant,
+    backoff: Duration,
+    blocked_until: Option<Instant>,
+}
+
+/// Paces requests per key, usually a host name (see [`host_key`]) or a
+/// Telegram account.
+///
+/// A key `name#n` is paced on its own but configured by `name`, so that e.g.
+/// each proxy or account gets the policy of the site; unknown keys use `*`,
+/// and are not limited if that is missing too.
+///
+/// After a 429/503 (or an explicit [`Self::backoff`]) the key is blocked for
+/// `Retry-After`, or for an exponential delay from 4 s up to 5 min that is
+/// reset by the next success.
+pub struct Limiter {
+    policies: HashMap<CompactString, Policy>,
+    states: Mutex<HashMap<CompactString, Arc<AsyncMutex<State>>>>,
+}
+
+impl Limiter {
+    /// `defaults` are the binary's own pacing, `rates` (from [`RateArgs`]) override them.
+    #[must_use]
+    pub fn new(defaults: &[(&str, Policy)], rates: Vec<Rate>) -> Self {
+        let mut policies = defaults
+            .iter()
+            .map(|(key, policy)| (CompactString::from(*key), *policy))
+            .collect::<HashMap<_, _>>();
+        policies.extend(rates.into_iter().map(|rate| (rate.key, rate.policy)));
+        Self { policies, states: Mutex::default() }
+    }
+
+    fn policy(&self, key: &str) -> Policy {
+        let class = key.split_once('#').map_or(key, |(class, _)| class);
+        self.policies
+            .get(key)
+            .or_else(|| self.policies.get(class))
+            .or_else(|| self.policies.get("*"))
+            .copied()
+            .unwrap_or(Policy::NONE)
+    }
+
+    fn state(&self, key: &str) -> Arc<AsyncMutex<State>> {
+        let mut states = self.states.lock();
+        if let Some(state) = states.get(key) {
+            return state.clone();
+        }
+        let policy = self.policy(key);
+        let state = Arc::new(AsyncMutex::new(State {
+            policy,
+            next: None,
+            tokens: if let Policy::Bucket { burst, .. } = policy { burst } else { 0.0 },
+            refilled: Instant::now(),
+            backoff: Duration::ZERO,
+            blocked_until: None,
+        }));
+        states.insert(key.into(), state.clone());
+        drop(states);
+        state
+    }
+
+    /// Waits until a request under `key` may start. Waiters are served in order.
+    pub async fn wait(&self, key: &str) {
+        let state = self.state(key);
+        let mut state = state.lock().await;
+
+        if let Some(until) = state.blocked_until.take() {
+            sleep_until(until).await;
+        }
+
+        match state.policy {
+            Policy::Delay { min, max } => {
+                if let Some(next) = state.next {
+                    sleep_until(next).await;
+                }
+                let delay = if min < max { rand::random_range(min..max) } else { min };
+                state.next = Some(Instant::now() + delay);
+            }
+            Policy::Bucket { rate, burst } => {
+                let now = Instant::now();
+                state.tokens = (now - state.refilled).as_secs_f64().mul_add(rate, state.tokens).min(burst);
+                state.refilled = now;
+                if state.tokens < 1.0 {
+                    sleep(Duration::from_secs_f64((1.0 - state.tokens) / rate)).await;
+                    state.tokens = 0.0;
+                    state.refilled = Instant::now();
+                } else {
+                    state.tokens -= 1.0;
+                }
+            }
+        }
+    }
+
+    /// Blocks `key` for `retry_after`, or for the next exponential step.
+    pub async fn backoff(&self, key: &str, retry_after: Option<Duration>) {
+        let state = self.state(key);
+        let mut state = state.lock().await;
+        state.backoff = (state.backoff * 2).clamp(BACKOFF_MIN, BACKOFF_MAX);
+        let delay = retry_after.unwrap_or(state.backoff).min(BACKOFF_MAX);
+        state.blocked_until = Some(Instant::now() + delay);
+        drop(state);
+        tracing::warn!(target: "limiter", "\x1b[33m{key}\x1b[0m backing off for {delay:?}");
+    }
+
+    pub async fn succeed(&self, key: &str) {
+        self.state(key).lock().await.backoff = Duration::ZERO;
+    }
+
+    /// Backs off after 429/503, honoring `Retry-After`, and resets the
+    /// backoff otherwise.
+    pub async fn feedback(&self, key: &str, response: &Response) {
+        self.feedback_of(key, response.status(), response.headers()).await;
+    }
+
+    async fn feedback_of(&self, key: &str, status: StatusCode, headers: &HeaderMap) {
+        if matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) {
+            self.backoff(key, retry_after(headers, SystemTime::now())).await;
+        } else {
+            self.succeed(key).await;
+        }
+    }
+}
+
+/// The `Retry-After` of a response, in seconds or as an HTTP date (a date in
+/// the past meaning no wait).
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+#[must_use]
+pub fn host_key(url: &Url) -> &str {
+    url.host_str().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delay(ms: u64) -> Policy {
+        Policy::delay(Duration::from_millis(ms))
+    }
+
+    fn bounds(policy: Policy) -> (Duration, Duration) {
+        match policy {
+            Policy::Delay { min, max } => (min, max),
+            Policy::Bucket { .. } => panic!("{policy:?} is not a delay"),
+        }
+    }
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(bounds("none".parse().unwrap()), (Duration::ZERO, Duration::ZERO));
+        assert_eq!(bounds("delay:250ms".parse().unwrap()), (Duration::from_millis(250), Duration::from_millis(250)));
+        assert_eq!(bounds("delay:1s..2.5s".parse().unwrap()), (Duration::from_secs(1), Duration::from_millis(2500)));
+
+        let Ok(Policy::Bucket { rate, burst }) = "bucket:2/s:4".parse() else { panic!() };
+        assert_eq!((rate, burst), (2.0, 4.0));
+        let Ok(Policy::Bucket { rate, burst }) = "bucket:0.5/s".parse() else { panic!() };
+        assert_eq!((rate, burst), (0.5, 1.0));
+
+        for bad in ["", "delay:250", "delay:2s..1s", "bucket:2", "bucket:0/s", "bucket:2/s:0", "fast"] {
+            assert!(bad.parse::<Policy>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rate() {
+        let rate = "*=bucket:2/s:4".parse::<Rate>().unwrap();
+        assert_eq!(rate.key, "*");
+        assert!(matches!(rate.policy, Policy::Bucket { .. }));
+
+        let rate = "telegram-bot#7=delay:3s".parse::<Rate>().unwrap();
+        assert_eq!(rate.key, "telegram-bot#7");
+        assert_eq!(bounds(rate.policy).0, Duration::from_secs(3));
+
+        assert!("accsmarket.com".parse::<Rate>().is_err());
+        assert!("accsmarket.com=slow".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn policy_of_key() {
+        let limiter = Limiter::new(&[("a.com", delay(1)), ("bot", delay(2))], Vec::new());
+        assert_eq!(bounds(limiter.policy("a.com")).0, Duration::from_millis(1));
+        assert_eq!(bounds(limiter.policy("bot#3")).0, Duration::from_millis(2));
+        assert_eq!(bounds(limiter.policy("b.com")).0, Duration::ZERO);
+
+        let rates = ["*=delay:5ms", "bot=delay:6ms", "bot#3=delay:7ms"].map(|r| r.parse().unwrap());
+        let limiter = Limiter::new(&[("a.com", delay(1)), ("bot", delay(2))], rates.into());
+        assert_eq!(bounds(limiter.policy("a.com")).0, Duration::from_millis(1));
+        assert_eq!(bounds(limiter.policy("bot#3")).0, Duration::from_millis(7));
+        assert_eq!(bounds(limiter.policy("bot#4")).0, Duration::from_millis(6));
+        assert_eq!(bounds(limiter.policy("b.com")).0, Duration::from_millis(5));
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(retry_after(&headers("120"), now), Some(Duration::from_mins(2)));
+        assert_eq!(retry_after(&headers(" 3 "), now), Some(Duration::from_secs(3)));
+
+        let later = httpdate::fmt_http_date(now + Duration::from_secs(90));
+        assert_eq!(retry_after(&headers(&later), now), Some(Duration::from_secs(90)));
+        let earlier = httpdate::fmt_http_date(now - Duration::from_secs(90));
+        assert_eq!(retry_after(&headers(&earlier), now), Some(Duration::ZERO));
+
+        assert_eq!(retry_after(&headers("soon"), now), None);
+        assert_eq!(retry_after(&HeaderMap::new(), now), None);
+    }
+
+    /// How long `key` is blocked for, from now.
+    async fn blocked(limiter: &Limiter, key: &str) -> Option<Duration> {
+        let until = limiter.state(key).lock().await.blocked_until?;
+        Some(until.saturating_duration_since(Instant::now()))
+    }
+
+    #[tokio::test]
+    async fn feedback() {
+        let limiter = Limiter::new(&[], Vec::new());
+        let near = |blocked: Option<Duration>, secs: u64| {
+            blocked.is_some_and(|d| d <= Duration::from_secs(secs) && d + Duration::from_secs(2) > Duration::from_secs(secs))
+        };
+
+        limiter.feedback_of("a", StatusCode::TOO_MANY_REQUESTS, &headers("30")).await;
+        assert!(near(blocked(&limiter, "a").await, 30));
+
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_mins(1));
+        limiter.feedback_of("b", StatusCode::SERVICE_UNAVAILABLE, &headers(&date)).await;
+        assert!(near(blocked(&limiter, "b").await, 60));
+
+        // exponential without Retry-After, reset by a success
+        limiter.feedback_of("c", StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()).await;
+        assert!(near(blocked(&limiter, "c").await, 4));
+        limiter.feedback_of("c", StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new()).await;
+        assert!(near(blocked(&limiter, "c").await, 8));
+        limiter.feedback_of("c", StatusCode::OK, &HeaderMap::new()).await;
+        assert_eq!(limiter.state("c").lock().await.backoff, Duration::ZERO);
+
+        limiter.feedback_of("d", StatusCode::NOT_FOUND, &headers("30")).await;
+        assert_eq!(blocked(&limiter, "d").await, None);
+    }
+}
@@ -0,0 +1,200 @@
+use core::fmt;
+use std::{collections::VecDeque, time::SystemTime};
+
+use futures_util::{StreamExt, future::BoxFuture, stream::FuturesOrdered};
+use reqwest::RequestBuilder;
+
+use super::retry::{Class, Failure, Retry};
+use crate::{
+    db::bulk::Upserted,
+    sink::{Record, Sink},
+};
+
+/// Produces the pages of one scrape, in order.
+pub trait Source: Send {
+    type Page: Send + Sync;
+
+    /// The next page, `None` once exhausted. A failed page does not end the scrape.
+    fn next(&mut self) -> impl Future<Output = Option<anyhow::Result<Self::Page>>> + Send;
+}
+
+/// Turns one page into records.
+pub trait Parser<P>: Sync {
+    type Output: Store;
+
+    fn parse(&self, page: &P) -> anyhow::Result<Self::Output>;
+}
+
+/// The records parsed from one page, written to a sink as a unit.
+pub trait Store: Send + Sync {
+    /// Number of records.
+    fn count(&self) -> usize;
+
+    fn store<K: Sink>(&self, sink: &K) -> impl Future<Output = anyhow::Result<Upserted>> + Send;
+}
+
+impl<R: Record> Store for Vec<R> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    async fn store<K: Sink>(&self, sink: &K) -> anyhow::Result<Upserted> {
+        sink.write(self).await
+    }
+}
+
+/// Written in order, e.g. categories before the items referencing them.
+impl<A: Store, B: Store> Store for (A, B) {
+    fn count(&self) -> usize {
+        self.0.count() + self.1.count()
+    }
+
+    async fn store<K: Sink>(&self, sink: &K) -> anyhow::Result<Upserted> {
+        let a = self.0.store(sink).await?;
+        let b = self.1.store(sink).await?;
+        Ok(Upserted { inserted: a.inserted + b.inserted, updated: a.updated + b.updated })
+    }
+}
+
+/// A response body, with the `Date` the server sent it at.
+#[derive(Debug)]
+pub struct Fetched<T> {
+    pub tag: T,
+    pub date: SystemTime,
+    pub body: String,
+}
+
+pub async fn fetch<T>(retry: &Retry<'_>, tag: T, request: RequestBuilder) -> anyhow::Result<Fetched<T>> {
+    let page = retry.text(request).await?;
+    let date = page.date().ok_or_else(|| anyhow::anyhow!("no date"))?;
+    Ok(Fetched { tag, date, body: page.body })
+}
+
+/// Sends queued requests through `retry` (and its limiter), one at a time
+/// unless [`Self::concurrency`] says otherwise. Pages come out in queue order.
+pub struct Requests<'a, T> {
+    queue: VecDeque<(T, RequestBuilder)>,
+    retry: &'a Retry<'a>,
+    concurrency: usize,
+    running: FuturesOrdered<BoxFuture<'a, anyhow::Result<Fetched<T>>>>,
+}
+
+impl<'a, T> Requests<'a, T> {
+    #[must_use]
+    pub fn new(retry: &'a Retry<'a>) -> Self {
+        Self { queue: VecDeque::new(), retry, concurrency: 1, running: FuturesOrdered::new() }
+    }
+
+    /// At most `n` requests in flight (at least 1).
+    #[must_use]
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    pub fn push(&mut self, tag: T, request: RequestBuilder) {
+        self.queue.push_back((tag, request));
+    }
+}
+
+impl<'a, T: Send + Sync + 'a> Source for Requests<'a, T> {
+    type Page = Fetched<T>;
+
+    async fn next(&mut self) -> Option<anyhow::Result<Fetched<T>>> {
+        while self.running.len() < self.concurrency && let Some((tag, request)) = self.queue.pop_front() {
+            self.running.push_back(Box::pin(fetch(self.retry, tag, request)));
+        }
+        self.running.next().await
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub pages: usize,
+    pub failed: usize,
+    /// Among `failed`, pages answered with 404/410.
+    pub gone: usize,
+    /// Among `failed`, pages replaced by an anti-bot challenge.
+    pub challenged: usize,
+    pub unparsed: usize,
+    pub unstored: usize,
+    pub records: usize,
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pages ({} failed, of which {} gone and {} challenged, {} unparsed, {} unstored), {} records ({} inserted, {} updated)",
+            self.pages, self.failed, self.gone, self.challenged, self.unparsed, self.unstored, self.records, self.inserted, self.updated,
+        )
+    }
+}
+
+/// Drives a [`Source`] through a [`Parser`] into a [`Sink`], logging under
+/// `target` and counting what went wrong instead of stopping.
+pub struct Pipeline<'a, K> {
+    target: &'a str,
+    sink: &'a K,
+}
+
+impl<'a, K: Sink> Pipeline<'a, K> {
+    pub const fn new(target: &'a str, sink: &'a K) -> Self {
+        Self { target, sink }
+    }
+
+    pub async fn run<S, P>(&self, mut source: S, parser: &P) -> Stats
+    where
+        S: Source,
+        P: Parser<S::Page>,
+    {
+        let target = self.target;
+        let mut stats = Stats::default();
+
+        while let Some(page) = source.next().await {
+            stats.pages += 1;
+            let n = stats.pages;
+
+            let page = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    stats.failed += 1;
+                    match e.downcast_ref::<Failure>().map(|f| f.class) {
+                        Some(Class::Gone) => stats.gone += 1,
+                        Some(Class::Challenge) => stats.challenged += 1,
+                        _ => (),
+                    }
+                    log::warn!(target: target, "[Page #{n}] fetch err: {e:?}");
+                    continue;
+                }
+            };
+
+            let records = match parser.parse(&page) {
+                Ok(records) => records,
+                Err(e) => {
+                    stats.unparsed += 1;
+                    log::warn!(target: target, "[Page #{n}] parse err: {e:?}");
+                    continue;
+                }
+            };
+            stats.records += records.count();
+
+            match records.store(self.sink).await {
+                Ok(upserted) => {
+                    stats.inserted += upserted.inserted;
+                    stats.updated += upserted.updated;
+                    log::info!(target: target, "\x1b[36m[Page #{n}] {} records, {} new\x1b[0m", records.count(), upserted.inserted);
+                }
+                Err(e) => {
+                    stats.unstored += 1;
+                    log::error!(target: target, "\x1b[31m[Page #{n}] store err: {e:?}\x1b[0m");
+                }
+            }
+        }
+
+        log::info!(target: target, "finished: {stats}");
+        stats
+    }
+}
@@ -0,0 +1,471 @@
+use core::{fmt, time::Duration};
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use reqwest::{
+    RequestBuilder, Response, StatusCode, Url,
+    header::{DATE, HeaderMap},
+};
+
+use super::{
+    archive::{Archive, Sent},
+    limit::{Limiter, host_key},
+};
+
+/// What kind of failure a fetch ended with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    /// Connection errors, timeouts and truncated bodies.
+    Network,
+    /// 429 and 5xx.
+    Overloaded,
+    /// 404 and 410: the item is gone, don't ask again.
+    Gone,
+    /// An anti-bot interstitial instead of the page.
+    Challenge,
+    /// Any other error status, or a request that cannot be sent.
+    Permanent,
+}
+
+impl Class {
+    /// Whether retrying the same request may succeed.
+    #[must_use]
+    pub const fn is_transient(self) -> bool {
+        matches!(self, Self::Network | Self::Overloaded)
+    }
+
+    /// The class of a received response, `None` if it is usable.
+    #[must_use]
+    pub fn of_response(res: &Response) -> Option<Self> {
+        Self::of_status(res.status(), res.headers())
+    }
+
+    fn of_status(status: StatusCode, headers: &HeaderMap) -> Option<Self> {
+        if headers.get("cf-mitigated").is_some_and(|v| v == "challenge") {
+            return Some(Self::Challenge);
+        }
+        Some(match status {
+            _ if status.is_success() => return None,
+            StatusCode::NOT_FOUND | StatusCode::GONE => Self::Gone,
+            StatusCode::TOO_MANY_REQUESTS => Self::Overloaded,
+            StatusCode::FORBIDDEN if headers.get("server").is_some_and(|v| v == "cloudflare") => Self::Challenge,
+            _ if status.is_server_error() => Self::Overloaded,
+            _ => Self::Permanent,
+        })
+    }
+
+    /// The class of the body of a response with a usable status, `None` if
+    /// it is the page: Cloudflare can answer 200 with its interstitial.
+    #[must_use]
+    pub fn of_body(body: &str) -> Option<Self> {
+        const CHALLENGE: [&str; 2] = ["<title>Just a moment...</title>", "window._cf_chl_opt"];
+
+        CHALLENGE.iter().any(|marker| body.contains(marker)).then_some(Self::Challenge)
+    }
+
+    #[must_use]
+    pub fn of_error(e: &reqwest::Error) -> Self {
+        if e.is_builder() || e.is_redirect() || e.is_decode() {
+            Self::Permanent
+        } else {
+            Self::Network
+        }
+    }
+}
+
+/// The last failure of a fetch, after `attempts` tries.
+#[derive(Debug)]
+pub struct Failure {
+    pub class: Class,
+    pub status: Option<StatusCode>,
+    pub error: Option<reqwest::Error>,
+    pub attempts: u32,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} after {} attempt(s)", self.class, self.attempts)?;
+        if let Some(status) = self.status {
+            write!(f, ", status {status}")?;
+        }
+        if let Some(ref e) = self.error {
+            write!(f, ": {e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        self.error.as_ref().map(|e| e as _)
+    }
+}
+
+pub type Outcome<T> = Result<T, Failure>;
+
+/// A successful response, read in full.
+#[derive(Debug)]
+pub struct Page {
+    pub url: Url,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Page {
+    /// The server `Date`, if sent and well-formed.
+    #[must_use]
+    pub fn date(&self) -> Option<SystemTime> {
+        date_of(&self.headers)
+    }
+}
+
+fn date_of(headers: &HeaderMap) -> Option<SystemTime> {
+    httpdate::parse_http_date(headers.get(DATE)?.to_str().ok()?).ok()
+}
+
+/// What [`Retry::execute`] got: the response, or all of it if asked to read
+/// the body.
+enum Fetched {
+    Response(Response),
+    Page(Page),
+}
+
+/// Sends requests again after transient failures, with capped exponential
+/// backoff and full jitter.
+///
+/// Retries draw from a budget shared by all requests: each retry costs one
+/// token and each success earns back a tenth, so a site that is down does not
+/// get hammered by every caller at once.
+///
+/// With an [`Archive`], every response read through [`Self::text`], and every
+/// failed one, is archived along with its request.
+pub struct Retry<'a> {
+    limiter: Option<&'a Limiter>,
+    archive: Option<&'a Archive>,
+    attempts: u32,
+    base: Duration,
+    cap: Duration,
+    /// In tenths of a retry.
+    budget: Mutex<u32>,
+    budget_max: u32,
+}
+
+impl<'a> Retry<'a> {
+    /// Up to 4 attempts, waits of at most 0.5 s, 1 s, 2 s (and at most 30 s),
+    /// 10 retries in the budget. Requests wait on `limiter`, if any, under
+    /// their host name.
+    #[must_use]
+    pub const fn new(limiter: Option<&'a Limiter>) -> Self {
+        Self {
+            limiter,
+            archive: None,
+            attempts: 4,
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            budget: Mutex::new(100),
+            budget_max: 100,
+        }
+    }
+
+    #[must_use]
+    pub const fn archive(mut self, archive: Option<&'a Archive>) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    #[must_use]
+    pub const fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    #[must_use]
+    pub const fn backoff(mut self, base: Duration, cap: Duration) -> Self {
+        self.base = base;
+        self.cap = cap;
+        self
+    }
+
+    #[must_use]
+    pub fn budget(mut self, retries: u32) -> Self {
+        self.budget_max = retries.saturating_mul(10);
+        *self.budget.get_mut() = self.budget_max;
+        self
+    }
+
+    fn withdraw(&self) -> bool {
+        let mut budget = self.budget.lock();
+        let ok = *budget >= 10;
+        if ok {
+            *budget -= 10;
+        }
+        ok
+    }
+
+    fn deposit(&self) {
+        let mut budget = self.budget.lock();
+        *budget = (*budget + 1).min(self.budget_max);
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.base.saturating_mul(1 << (attempt - 1).min(16)).min(self.cap);
+        rand::random_range(Duration::ZERO..=exp)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Outcome<Response> {
+        self.send_as(None, request).await
+    }
+
+    /// Same as [`Self::send`], pacing under `key` instead of the host name.
+    pub async fn send_as(&self, key: Option<&str>, request: RequestBuilder) -> Outcome<Response> {
+        match self.execute(key, request, false).await? {
+            Fetched::Response(res) => Ok(res),
+            Fetched::Page(_) => unreachable!("bodies are only read for `text`"),
+        }
+    }
+
+    /// Sends `request` and reads the body, archiving both. A body that cannot
+    /// be read in full is retried like a failed request.
+    pub async fn text(&self, request: RequestBuilder) -> Outcome<Page> {
+        self.text_as(None, request).await
+    }
+
+    /// Same as [`Self::text`], pacing under `key` instead of the host name.
+    pub async fn text_as(&self, key: Option<&str>, request: RequestBuilder) -> Outcome<Page> {
+        match self.execute(key, request, true).await? {
+            Fetched::Page(page) => Ok(page),
+            Fetched::Response(_) => unreachable!("bodies are always read for `text`"),
+        }
+    }
+
+    /// Reads the body of `res`, archiving it if `sent` was recorded. Archive
+    /// errors are logged, not returned.
+    async fn read(&self, sent: Option<&Sent>, res: Response) -> reqwest::Result<Page> {
+        let url = res.url().clone();
+        let status = res.status();
+        let version = res.version();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+
+        if let Some(archive) = self.archive && let Some(sent) = sent {
+            let date = date_of(&headers).unwrap_or_else(SystemTime::now);
+            if let Err(e) = archive.exchange(url.as_str(), sent, status, version, &headers, &body, date) {
+                tracing::error!(target: "archive", "\x1b[31m{url}\x1b[0m {e}");
+            }
+        }
+
+        Ok(Page { url, status, headers, body: String::from_utf8_lossy(&body).into_owned() })
+    }
+
+    /// Sends `request` until it succeeds, reading the body too if `read`.
+    async fn execute(&self, key: Option<&str>, request: RequestBuilder, read: bool) -> Outcome<Fetched> {
+        let (client, request) = request.build_split();
+        let request = request.map_err(|e| Failure { class: Class::of_error(&e), status: None, error: Some(e), attempts: 0 })?;
+        let key = key.unwrap_or_else(|| host_key(request.url())).to_owned();
+
+        let mut spare = Some(request);
+        let mut attempts = 0;
+        while let Some(request) = spare.take() {
+            spare = request.try_clone();
+            attempts += 1;
+
+            if let Some(limiter) = self.limiter {
+                limiter.wait(&key).await;
+            }
+            let sent = self.archive.map(|_| Sent::of(&request));
+            let failure = match client.execute(request).await {
+                Ok(res) => {
+                    if let Some(limiter) = self.limiter {
+                        limiter.feedback(&key, &res).await;
+                    }
+                    let status = res.status();
+                    if let Some(class) = Class::of_response(&res) {
+                        if sent.is_some() {
+                            // the body of an error page is kept for the archive only
+                            let _ = self.read(sent.as_ref(), res).await;
+                        }
+                        Failure { class, status: Some(status), error: None, attempts }
+                    } else if !read {
+                        self.deposit();
+                        return Ok(Fetched::Response(res));
+                    } else {
+                        match self.read(sent.as_ref(), res).await {
+                            Ok(page) => match Class::of_body(&page.body) {
+                                None => {
+                                    self.deposit();
+                                    return Ok(Fetched::Page(page));
+                                }
+                                Some(class) => Failure { class, status: Some(status), error: None, attempts },
+                            },
+                            // a body cut short, which reqwest reports as a decode error
+                            Err(e) => Failure { class: Class::Network, status: Some(status), error: Some(e), attempts },
+                        }
+                    }
+                }
+                Err(e) => Failure { class: Class::of_error(&e), status: None, error: Some(e), attempts },
+            };
+
+            if !failure.class.is_transient() || attempts >= self.attempts || spare.is_none() || !self.withdraw() {
+                return Err(failure);
+            }
+            let delay = self.delay(attempts);
+            tracing::warn!(target: "retry", "\x1b[33m{key}\x1b[0m {failure}, retrying in {delay:?}");
+            tokio::time::sleep(delay).await;
+        }
+
+        unreachable!("the loop only ends by returning")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn class(status: u16, headers: &[(&'static str, &'static str)]) -> Option<Class> {
+        let headers = headers
+            .iter()
+            .map(|&(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect::<HeaderMap>();
+        Class::of_status(StatusCode::from_u16(status).unwrap(), &headers)
+    }
+
+    #[test]
+    fn classify_status() {
+        assert_eq!(class(200, &[]), None);
+        assert_eq!(class(429, &[]), Some(Class::Overloaded));
+        assert_eq!(class(503, &[]), Some(Class::Overloaded));
+        assert_eq!(class(500, &[]), Some(Class::Overloaded));
+        assert_eq!(class(404, &[]), Some(Class::Gone));
+        assert_eq!(class(410, &[]), Some(Class::Gone));
+        assert_eq!(class(403, &[]), Some(Class::Permanent));
+        assert_eq!(class(403, &[("server", "cloudflare")]), Some(Class::Challenge));
+        assert_eq!(class(200, &[("cf-mitigated", "challenge")]), Some(Class::Challenge));
+        assert_eq!(class(400, &[]), Some(Class::Permanent));
+
+        let interstitial = "<!DOCTYPE html><html><head><title>Just a moment...</title></head><body></body></html>";
+        assert_eq!(Class::of_body(interstitial), Some(Class::Challenge));
+        assert_eq!(Class::of_body("<script>window._cf_chl_opt = {cType: 'managed'};</script>"), Some(Class::Challenge));
+        assert_eq!(Class::of_body("<title>Services</title> Just a moment, loading"), None);
+
+        assert!(Class::Network.is_transient() && Class::Overloaded.is_transient());
+        assert!(!Class::Gone.is_transient() && !Class::Challenge.is_transient() && !Class::Permanent.is_transient());
+    }
+
+    #[tokio::test]
+    async fn classify_error() {
+        // nothing listens on port 1
+        let e = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+        assert_eq!(Class::of_error(&e), Class::Network);
+
+        let e = reqwest::Client::new().get("no url").build().unwrap_err();
+        assert_eq!(Class::of_error(&e), Class::Permanent);
+    }
+
+    #[test]
+    fn delay_bounds() {
+        let retry = Retry::new(None).backoff(Duration::from_millis(100), Duration::from_secs(1));
+        for _ in 0..100 {
+            assert!(retry.delay(1) <= Duration::from_millis(100));
+            assert!(retry.delay(2) <= Duration::from_millis(200));
+            assert!(retry.delay(4) <= Duration::from_millis(800));
+            assert!(retry.delay(5) <= Duration::from_secs(1));
+            assert!(retry.delay(64) <= Duration::from_secs(1));
+        }
+        assert!((0..100).map(|_| retry.delay(5)).any(|d| d > Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn budget() {
+        let retry = Retry::new(None).budget(2);
+        assert!(retry.withdraw());
+        assert!(retry.withdraw());
+        assert!(!retry.withdraw());
+
+        // ten successes earn a retry back
+        for _ in 0..9 {
+            retry.deposit();
+        }
+        assert!(!retry.withdraw());
+        retry.deposit();
+        assert!(retry.withdraw());
+
+        // up to the initial budget
+        for _ in 0..100 {
+            retry.deposit();
+        }
+        assert!(retry.withdraw() && retry.withdraw() && !retry.withdraw());
+    }
+
+    const UNAVAILABLE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+
+    /// Answers every request with `answer`, counting them.
+    fn serve(answer: &'static [u8]) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap()).parse().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(Result::ok) {
+                let _ = stream.read(&mut [0; 4096]);
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = stream.write_all(answer);
+            }
+        });
+        (url, hits)
+    }
+
+    #[tokio::test]
+    async fn budget_stops_retries() {
+        let (url, hits) = serve(UNAVAILABLE);
+        let client = reqwest::Client::new();
+        let retry = Retry::new(None).backoff(Duration::from_millis(1), Duration::from_millis(1)).budget(2);
+
+        // 3 attempts for the first request, then the budget is spent
+        let failure = retry.send(client.get(url.clone())).await.unwrap_err();
+        assert_eq!((failure.class, failure.status, failure.attempts), (Class::Overloaded, Some(StatusCode::SERVICE_UNAVAILABLE), 3));
+        let failure = retry.send(client.get(url.clone())).await.unwrap_err();
+        assert_eq!(failure.attempts, 1);
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+        let retry = Retry::new(None).backoff(Duration::from_millis(1), Duration::from_millis(1)).attempts(2);
+        let failure = retry.send(client.get(url)).await.unwrap_err();
+        assert_eq!(failure.attempts, 2);
+        assert_eq!(hits.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn truncated_body() {
+        // the connection closes 90 bytes short of the body
+        let (url, hits) = serve(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\nconnection: close\r\n\r\n0123456789");
+        let retry = Retry::new(None).backoff(Duration::from_millis(1), Duration::from_millis(1)).attempts(3);
+        let failure = retry.text(reqwest::Client::new().get(url)).await.unwrap_err();
+        assert_eq!((failure.class, failure.status, failure.attempts), (Class::Network, Some(StatusCode::OK), 3));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn challenge_body() {
+        let (url, hits) = serve(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 38\r\nconnection: close\r\n\r\n<title>Just a moment...</title> <html>",
+        );
+        let retry = Retry::new(None).backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let failure = retry.text(reqwest::Client::new().get(url.clone())).await.unwrap_err();
+        assert_eq!((failure.class, failure.status, failure.attempts), (Class::Challenge, Some(StatusCode::OK), 1));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // only read bodies are looked at
+        assert!(retry.send(reqwest::Client::new().get(url)).await.is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use core::{fmt::Debug, str::FromStr};
+use std::{path::PathBuf, time::SystemTime};
+
+use serde::{Serialize, Serializer};
+
+use crate::db::{
+    DBArgs,
+    bulk::{Bulk, Upserted},
+};
+
+mod diff;
+mod jsonl;
+mod postgres;
+mod sqlite;
+mod stdout;
+
+pub use diff::Diff;
+pub use jsonl::JsonLines;
+pub use postgres::Postgres;
+pub use sqlite::Sqlite;
+pub use stdout::Stdout;
+
+/// A row produced by a scraper.
+///
+/// The serialized field names are the column names, so every sink stores the
+/// same shape as the Postgres table.
+pub trait Record: Serialize + Debug + Send + Sync + Sized {
+    /// Schema-qualified table name, e.g. `accs.market`.
+    const TABLE: &'static str;
+    /// Serialized fields identifying a row; a later row with the same key
+    /// replaces the earlier one. Empty if rows are only ever appended.
+    const KEY: &'static [&'static str];
+    /// Serialized fields that a later row with the same key leaves as they
+    /// were, e.g. a first-seen time.
+    const KEEP: &'static [&'static str] = &[];
+
+    /// The Postgres statement writing `rows`.
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self>;
+}
+
+pub trait Sink: Send + Sync {
+    fn write<R: Record>(&self, rows: &[R]) -> impl Future<Output = anyhow::Result<Upserted>> + Send;
+}
+
+/// The sink picked on the command line.
+pub enum AnySink {
+    Postgres(Postgres),
+    JsonLines(JsonLines),
+    Sqlite(Sqlite),
+    Diff(Diff),
+    Stdout(Stdout),
+}
+
+impl Sink for AnySink {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        match self {
+            Self::Postgres(sink) => sink.write(rows).await,
+            Self::JsonLines(sink) => sink.write(rows).await,
+            Self::Sqlite(sink) => sink.write(rows).await,
+            Self::Diff(sink) => sink.write(rows).await,
+            Self::Stdout(sink) => sink.write(rows).await,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SinkSpec {
+    Postgres,
+    JsonLines(PathBuf),
+    Sqlite(PathBuf),
+    Diff(PathBuf),
+    Stdout,
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "postgres" {
+            Ok(Self::Postgres)
+        } else if let Some(dir) = s.strip_prefix("jsonl:") {
+            Ok(Self::JsonLines(dir.into()))
+        } else if let Some(file) = s.strip_prefix("sqlite:") {
+            Ok(Self::Sqlite(file.into()))
+        } else if let Some(dir) = s.strip_prefix("diff:") {
+            Ok(Self::Diff(dir.into()))
+        } else if s == "stdout" {
+            Ok(Self::Stdout)
+        } else {
+            Err(format!("unknown sink {s:?}, expected postgres, jsonl:<dir>, sqlite:<file>, diff:<dir> or stdout"))
+        }
+    }
+}
+
+impl SinkSpec {
+    /// Opens the sink; for Postgres, the database must already be initialized.
+    pub fn open(&self) -> anyhow::Result<AnySink> {
+        Ok(match self {
+            Self::Postgres => AnySink::Postgres(Postgres::default()),
+            Self::JsonLines(dir) => AnySink::JsonLines(JsonLines::open(dir.clone())?),
+            Self::Sqlite(file) => AnySink::Sqlite(Sqlite::open(file)?),
+            Self::Diff(dir) => AnySink::Diff(Diff::open(dir.clone())),
+            Self::Stdout => AnySink::Stdout(Stdout),
+        })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SinkArgs {
+    #[arg(
+        long,
+        global = true,
+        env = "USCR_SINK",
+        default_value = "postgres",
+        value_name = "sink",
+        help = "Where records are written: postgres, jsonl:<dir>, sqlite:<file>, diff:<dir> or stdout"
+    )]
+    pub sink: SinkSpec,
+    #[arg(long, global = true, help = "Write every batch to Postgres with binary COPY through a staging table, not only the large ones")]
+    pub copy: bool,
+}
+
+impl SinkArgs {
+    /// Opens the sink, connecting to the database only if it is the target.
+    pub async fn open(&self, db: &DBArgs) -> anyhow::Result<AnySink> {
+        if matches!(self.sink, SinkSpec::Postgres) {
+            crate::db::init_db(db.config()?).await?;
+            return Ok(AnySink::Postgres(Postgres { copy: self.copy }));
+        }
+        self.sink.open()
+    }
+}
+
+/// `serialize_with` helper writing a [`SystemTime`] as Unix seconds.
+pub fn timestamp<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs().cast_signed(),
+        Err(e) => -e.duration().as_secs().cast_signed(),
+    };
+    serializer.serialize_i64(secs)
+}
@@ -0,0 +1,89 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{Record, Sink};
+use crate::db::bulk::Upserted;
+
+/// The last baseline row of each key.
+type Baseline = HashMap<String, Value>;
+
+/// Writes nothing; compares each record with the baseline in
+/// `<dir>/<table>.jsonl` (as written by [`super::JsonLines`]) and prints the
+/// records that are new or differ as JSON Lines on stdout.
+///
+/// New records count as inserted, changed ones as updated.
+pub struct Diff {
+    dir: PathBuf,
+    tables: Mutex<HashMap<&'static str, Baseline>>,
+}
+
+#[derive(Serialize)]
+struct Change<'a> {
+    table: &'static str,
+    key: &'a [&'a Value],
+    old: Option<&'a Value>,
+    new: &'a Value,
+}
+
+fn key_of(keys: &[&str], value: &Value) -> String {
+    let key = keys.iter().map(|k| &value[k]).collect::<Vec<_>>();
+    serde_json::to_string(&key).unwrap_or_default()
+}
+
+impl Diff {
+    #[must_use]
+    pub fn open(dir: PathBuf) -> Self {
+        Self { dir, tables: Mutex::default() }
+    }
+
+    fn load(&self, table: &str, keys: &[&str]) -> io::Result<Baseline> {
+        let file = match File::open(self.dir.join(format!("{table}.jsonl"))) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Baseline::new()),
+            Err(e) => return Err(e),
+        };
+        let mut baseline = Baseline::new();
+        for line in BufReader::new(file).lines() {
+            let value = serde_json::from_str::<Value>(&line?).map_err(io::Error::other)?;
+            baseline.insert(key_of(keys, &value), value);
+        }
+        Ok(baseline)
+    }
+}
+
+impl Sink for Diff {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        let mut tables = self.tables.lock();
+        let baseline = match tables.entry(R::TABLE) {
+            hashbrown::hash_map::Entry::Occupied(e) => e.into_mut(),
+            hashbrown::hash_map::Entry::Vacant(e) => e.insert(self.load(R::TABLE, R::KEY)?),
+        };
+
+        let mut upserted = Upserted::default();
+        let mut out = io::stdout().lock();
+        for row in rows {
+            let new = serde_json::to_value(row)?;
+            let old = baseline.get(&key_of(R::KEY, &new));
+            match old {
+                None => upserted.inserted += 1,
+                Some(old) if *old != new => upserted.updated += 1,
+                Some(_) => continue,
+            }
+            let key = R::KEY.iter().map(|k| &new[k]).collect::<Vec<_>>();
+            serde_json::to_writer(&mut out, &Change { table: R::TABLE, key: &key, old, new: &new })?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        drop(tables);
+
+        Ok(upserted)
+    }
+}
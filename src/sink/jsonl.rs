@@ -0,0 +1,63 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use hashbrown::{HashMap, HashSet};
+use parking_lot::Mutex;
+
+use super::{Record, Sink};
+use crate::db::bulk::Upserted;
+
+type Table = (BufWriter<File>, HashSet<String>);
+
+/// Appends every record to `<dir>/<table>.jsonl`.
+///
+/// Nothing is ever replaced on disk; a row whose key was already written by
+/// this process is counted as updated, so that scrapers stopping on "nothing
+/// new" still stop.
+pub struct JsonLines {
+    dir: PathBuf,
+    tables: Mutex<HashMap<&'static str, Table>>,
+}
+
+impl JsonLines {
+    pub fn open(dir: PathBuf) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, tables: Mutex::default() })
+    }
+}
+
+impl Sink for JsonLines {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        let mut tables = self.tables.lock();
+        let (writer, seen) = match tables.entry(R::TABLE) {
+            hashbrown::hash_map::Entry::Occupied(e) => e.into_mut(),
+            hashbrown::hash_map::Entry::Vacant(e) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.dir.join(format!("{}.jsonl", R::TABLE)))?;
+                e.insert((BufWriter::new(file), HashSet::new()))
+            }
+        };
+
+        let mut upserted = Upserted::default();
+        for row in rows {
+            let value = serde_json::to_value(row)?;
+            let key = R::KEY.iter().map(|k| &value[k]).collect::<Vec<_>>();
+            if R::KEY.is_empty() || seen.insert(serde_json::to_string(&key)?) {
+                upserted.inserted += 1;
+            } else {
+                upserted.updated += 1;
+            }
+            serde_json::to_writer(&mut *writer, &value)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(tables);
+
+        Ok(upserted)
+    }
+}
@@ -0,0 +1,26 @@
+use super::{Record, Sink};
+use crate::db::{bulk::Upserted, get_connection};
+
+/// Batches of at least this many rows are written with
+/// [`Bulk::copy`](crate::db::bulk::Bulk::copy): below it, creating and
+/// merging the staging table costs more round trips than it saves.
+pub const COPY_ROWS: usize = 5000;
+
+/// Writes through [`Record::bulk`] on a pooled connection.
+#[derive(Default)]
+pub struct Postgres {
+    /// Use [`Bulk::copy`](crate::db::bulk::Bulk::copy) for every batch, not
+    /// only those of [`COPY_ROWS`] rows or more.
+    pub copy: bool,
+}
+
+impl Sink for Postgres {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        if rows.is_empty() {
+            return Ok(Upserted::default());
+        }
+        let mut conn = get_connection().await?;
+        let bulk = R::bulk(rows);
+        Ok(if self.copy || rows.len() >= COPY_ROWS { bulk.copy(&mut conn).await? } else { bulk.execute(&mut conn).await? })
+    }
+}
@@ -0,0 +1,108 @@
+use core::fmt::Write;
+use std::path::Path;
+
+use hashbrown::HashSet;
+use parking_lot::Mutex;
+use rusqlite::{Connection, params_from_iter, types::Value as SqlValue};
+use serde_json::Value;
+
+use super::{Record, Sink};
+use crate::db::bulk::Upserted;
+
+/// Writes into an sqlite database file, one table per [`Record::TABLE`] (the dot is kept
+/// in the quoted name).
+///
+/// Tables are created on first use with the columns of the first row and no
+/// declared types; nested values are stored as JSON text.
+pub struct Sqlite {
+    conn: Mutex<Connection>,
+    tables: Mutex<HashSet<&'static str>>,
+}
+
+impl Sqlite {
+    pub fn open(file: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(file)?;
+        conn.pragma_update(None, "journal_mode", "wal")?;
+        Ok(Self { conn: Mutex::new(conn), tables: Mutex::default() })
+    }
+}
+
+fn to_sql(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b.into()),
+        Value::Number(n) => n.as_i64().map_or_else(|| SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)), SqlValue::Integer),
+        Value::String(s) => SqlValue::Text(s),
+        value @ (Value::Array(_) | Value::Object(_)) => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn quote_list<'a, I: Iterator<Item = &'a str>>(names: I) -> String {
+    names.map(|name| format!("\"{name}\"")).collect::<Vec<_>>().join(", ")
+}
+
+impl Sink for Sqlite {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        let rows = rows
+            .iter()
+            .map(|row| match serde_json::to_value(row)? {
+                Value::Object(map) => Ok(map),
+                _ => anyhow::bail!("{} records must serialize to objects", R::TABLE),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let Some(first) = rows.first() else {
+            return Ok(Upserted::default());
+        };
+        let columns = first.keys().cloned().collect::<Vec<_>>();
+        let table = R::TABLE;
+
+        let mut conn = self.conn.lock();
+        if self.tables.lock().insert(table) {
+            let mut sql = format!("create table if not exists \"{table}\" ({});", quote_list(columns.iter().map(String::as_str)));
+            if !R::KEY.is_empty() {
+                let _ = write!(sql, "create unique index if not exists \"{table}_key\" on \"{table}\" ({});", quote_list(R::KEY.iter().copied()));
+            }
+            conn.execute_batch(&sql)?;
+        }
+
+        let placeholders = (1..=columns.len()).map(|i| format!("?{i}")).collect::<Vec<_>>().join(", ");
+        let mut insert = format!("insert into \"{table}\" ({}) values ({placeholders})", quote_list(columns.iter().map(String::as_str)));
+        if !R::KEY.is_empty() {
+            let set = columns
+                .iter()
+                .filter(|c| !R::KEY.contains(&c.as_str()) && !R::KEEP.contains(&c.as_str()))
+                .map(|c| format!("\"{c}\" = excluded.\"{c}\""))
+                .collect::<Vec<_>>();
+            if set.is_empty() {
+                let _ = write!(insert, " on conflict ({}) do nothing", quote_list(R::KEY.iter().copied()));
+            } else {
+                let _ = write!(insert, " on conflict ({}) do update set {}", quote_list(R::KEY.iter().copied()), set.join(", "));
+            }
+        }
+        let exists = format!(
+            "select 1 from \"{table}\" where {}",
+            R::KEY.iter().enumerate().map(|(i, k)| format!("\"{k}\" is ?{}", i + 1)).collect::<Vec<_>>().join(" and "),
+        );
+
+        let txn = conn.transaction()?;
+        let mut upserted = Upserted::default();
+        {
+            let mut insert = txn.prepare_cached(&insert)?;
+            let mut exists = (!R::KEY.is_empty()).then(|| txn.prepare_cached(&exists)).transpose()?;
+            for mut row in rows {
+                if let Some(ref mut exists) = exists
+                    && exists.exists(params_from_iter(R::KEY.iter().map(|k| to_sql(row.get(*k).cloned().unwrap_or_default()))))?
+                {
+                    upserted.updated += 1;
+                } else {
+                    upserted.inserted += 1;
+                }
+                insert.execute(params_from_iter(columns.iter().map(|c| to_sql(row.remove(c).unwrap_or_default()))))?;
+            }
+        }
+        txn.commit()?;
+        drop(conn);
+
+        Ok(upserted)
+    }
+}
@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::{Record, Sink};
+use crate::db::bulk::Upserted;
+
+/// Writes nothing; prints every record to stdout as a JSON line
+/// `{"table": ..., "record": ...}`, each counted as inserted.
+pub struct Stdout;
+
+#[derive(Serialize)]
+struct Line<'a, R> {
+    table: &'static str,
+    record: &'a R,
+}
+
+impl Sink for Stdout {
+    async fn write<R: Record>(&self, rows: &[R]) -> anyhow::Result<Upserted> {
+        let mut out = io::stdout().lock();
+        for record in rows {
+            serde_json::to_writer(&mut out, &Line { table: R::TABLE, record })?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+
+        Ok(Upserted { inserted: rows.len(), updated: 0 })
+    }
+}
@@ -15,66 +15,6 @@ pub struct DBWrapper<'a, const N: usize> {
     pub stmts: [&'a Statement; N],
 }
 
-pub async fn insert_channels<C>(channels: C, conn: &mut Client) -> DBResult<()>
-where
-    C: Iterator<Item = Channel> + Send,
-{
-    const SQL: &str = "insert into telegram.channel (id, name, min_message_id, max_message_id, access_hash, last_fetch, app_id) values ($1, $2, 0, 0, $3, (now() at time zone 'UTC') - interval '1 day', $4) on conflict (id) do update set name = excluded.name, access_hash = excluded.access_hash, app_id = excluded.app_id";
-
-    let stmt = conn.prepare_static(SQL.into()).await?;
-    let txn = conn.transaction().await?;
-
-    let mut n = 0;
-    let mut N = 0;
-    for channel in channels {
-        match txn.execute(&stmt, &[
-            &channel.id,
-            &&*channel.name,
-            &channel.access_hash,
-            &channel.app_id,
-        ]).await {
-            Ok(r) => n += r,
-            Err(e) => tracing::error!(target: "telegram-insert-channel", ?e),
-        }
-        N += 1;
-    }
-
-    txn.commit().await?;
-
-    tracing::info!(target: "telegram-insert-channel", "{n}/{N} records upserted.");
-    Ok(())
-}
-
-pub async fn insert_users<C>(users: C, conn: &mut Client) -> DBResult<()>
-where
-    C: Iterator<Item = User> + Send,
-{
-    const SQL: &str = "insert into telegram.bots (id, name, access_hash, app_id) values ($1, $2, $3, $4) on conflict (id) do update set name = excluded.name, access_hash = excluded.access_hash, app_id = excluded.app_id";
-
-    let stmt = conn.prepare_static(SQL.into()).await?;
-    let txn = conn.transaction().await?;
-
-    let mut n = 0;
-    let mut N = 0;
-    for user in users {
-        match txn.execute(&stmt, &[
-            &user.peer.id,
-            &&*user.peer.name,
-            &user.peer.access_hash,
-            &user.peer.app_id,
-        ]).await {
-            Ok(r) => n += r,
-            Err(e) => tracing::error!(target: "telegram-insert-user", ?e),
-        }
-        N += 1;
-    }
-
-    txn.commit().await?;
-
-    tracing::info!(target: "telegram-insert-user", "{n}/{N} records upserted.");
-    Ok(())
-}
-
 async fn get_all_peers_from_db_inner(sql: &'static str, conn: &mut Client) -> DBResult<Vec<Channel>> {
     let stmt = conn.prepare_static(sql.into()).await?;
     let rows = conn.query(&stmt, &[]).await?;
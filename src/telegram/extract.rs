@@ -7,11 +7,14 @@ use std::{
 use compact_str::CompactString;
 use futures_util::TryStreamExt;
 use hashbrown::{HashMap, hash_map::RawEntryMut, hash_set::HashSet};
-use tokio_postgres::{Client, types::ToSql};
+use tokio_postgres::{
+    Client,
+    types::{ToSql, Type},
+};
 use unicase::UniCase;
 use uscr::{
-    db::{DBError, DBResult, ToSqlIter, get_connection},
-    util::{box_io_error, xmax_to_success},
+    db::{DBError, DBResult, bulk::Bulk, get_connection},
+    util::box_io_error,
 };
 
 const fn idc(ch: u8) -> bool {
@@ -91,8 +94,6 @@ impl Inspector {
     }
 
     async fn commit(&mut self, conn: &mut Client) -> DBResult<()> {
-        const SQL: &str = "with tmp_insert(c1, m, c2) as (select * from unnest($1::bigint[], $2::integer[], $3::bigint[])) insert into telegram.link (c1, message_id, c2) select c1, m, c2 from tmp_insert on conflict (c1, message_id, c2) do nothing returning xmax";
-
         let mut batch = Vec::with_capacity(self.es.len());
         for (channel_id, message_id, result) in core::mem::take(&mut self.es) {
             if let Some(&id2) = self.map.get(&result) && channel_id != id2 { // self reference
@@ -104,16 +105,15 @@ impl Inspector {
             return Ok(());
         }
 
-        let stmt = conn.prepare_static(SQL.into()).await?;
-        let rows = conn
-            .query(&stmt, &[
-                &ToSqlIter(batch.iter().map(|x| x.0)),
-                &ToSqlIter(batch.iter().map(|x| x.1)),
-                &ToSqlIter(batch.iter().map(|x| x.2)),
-            ])
+        let upserted = Bulk::new("telegram.link", &batch)
+            .column("c1", Type::INT8, |x| x.0)
+            .column("message_id", Type::INT4, |x| x.1)
+            .column("c2", Type::INT8, |x| x.2)
+            .on_conflict_nothing(&["c1", "message_id", "c2"])
+            .execute(conn)
             .await?;
 
-        tracing::info!(target: "telegram-committer", "\x1b[32m{}\x1b[0m/\x1b[33m{}\x1b[0m links added.", xmax_to_success(rows.iter()), batch.len());
+        tracing::info!(target: "telegram-committer", "\x1b[32m{}\x1b[0m/\x1b[33m{}\x1b[0m links added.", upserted.inserted, batch.len());
 
         Ok(())
     }
@@ -39,6 +39,12 @@ struct Args {
         help = "Flood sleep threshold"
     )]
     flood_sleep_threshold: u32,
+    #[command(flatten)]
+    db: uscr::db::DBArgs,
+    #[command(flatten)]
+    sink: uscr::sink::SinkArgs,
+    #[command(flatten)]
+    rate: uscr::scrape::limit::RateArgs,
 }
 
 #[derive(clap::Subcommand)]
@@ -65,16 +71,40 @@ enum Commands {
     },
 }
 
+impl Commands {
+    /// Whether the command reads the database (the channels, bots or peers
+    /// stored so far), whatever the sink.
+    const fn reads_db(&self) -> bool {
+        !matches!(self, Self::Ping { force: true, .. })
+    }
+}
+
 #[tokio::main]
 #[allow(clippy::too_many_lines)]
 async fn main() -> anyhow::Result<()> {
     use clap::Parser;
     use hashbrown::{HashMap, HashSet};
+    use uscr::sink::{AnySink, Sink};
 
     pretty_env_logger::init_timed();
-    uscr::db::init_db().await;
 
     let args = Args::parse();
+    let sink = args.sink.open(&args.db).await?;
+    if args.command.reads_db() && !matches!(sink, AnySink::Postgres(_)) {
+        uscr::db::init_db(args.db.config()?).await?;
+    }
+    let limiter = {
+        use core::time::Duration;
+        use uscr::scrape::limit::{Limiter, Policy};
+
+        Limiter::new(
+            &[
+                ("telegram-bot", Policy::delay(const { Duration::from_millis(3141) })),
+                ("telegram-content", Policy::delay(const { Duration::from_millis(180) })),
+            ],
+            args.rate.rates,
+        )
+    };
     std::fs::create_dir_all(&args.session)?;
 
     let mut clients = telegram::client::init_clients_from_map(
@@ -83,14 +113,12 @@ async fn main() -> anyhow::Result<()> {
         args.flood_sleep_threshold,
     ).await;
 
-    let mut conn = uscr::db::get_connection().await?;
-
     match args.command {
         Commands::Ping { channels: raw_channels, force } => {
             let searched = if force {
                 HashMap::default()
             } else {
-                db::get_searched_peers(&mut conn).await?
+                db::get_searched_peers(&mut *uscr::db::get_connection().await?).await?
             };
 
             let mut channels = HashMap::with_capacity(raw_channels.len());
@@ -116,7 +144,7 @@ async fn main() -> anyhow::Result<()> {
                 let (z_channels, z_users) = ping::work(
                     name_or_hashes.into_iter().map(unicase::UniCase::into_inner).collect(),
                     clients.iter(),
-                    &mut conn,
+                    &sink,
                 ).await;
                 for channel in z_channels {
                     channels.insert(channel.id, channel);
@@ -127,12 +155,17 @@ async fn main() -> anyhow::Result<()> {
             }
 
             tracing::info!("{channels:#?}");
-            db::insert_channels(channels.into_values(), &mut conn).await?;
+            let channels = channels.into_values().collect::<Vec<_>>();
+            let upserted = sink.write(&channels).await?;
+            tracing::info!(target: "telegram-insert-channel", "{}/{} records upserted.", upserted.inserted + upserted.updated, channels.len());
 
             tracing::info!("{users:#?}");
-            db::insert_users(users.into_values().filter(telegram::User::maybe_bot), &mut conn).await?;
+            let users = users.into_values().filter(telegram::User::maybe_bot).collect::<Vec<_>>();
+            let upserted = sink.write(&users).await?;
+            tracing::info!(target: "telegram-insert-user", "{}/{} records upserted.", upserted.inserted + upserted.updated, users.len());
         }
         Commands::Content { channels: channels_filt, limit } => {
+            let mut conn = uscr::db::get_connection().await?;
             let mut channels = db::get_all_channels_from_db(&mut conn).await?;
             if !channels_filt.is_empty() {
                 let filt = channels_filt.into_iter().collect::<HashSet<i64>>();
@@ -152,27 +185,28 @@ async fn main() -> anyhow::Result<()> {
 
             let db = {
                 super let stmt_get_range = conn.prepare_static("select min_message_id, max_message_id from telegram.channel where id = $1".into()).await?;
-                super let stmt_insert_msg = conn.prepare_static("with tmp_insert(m, d) as (select * from unnest($1::integer[], $3::jsonb[])) insert into telegram.message (id, message_id, channel_id, data) select ($2::bigint << 32) | m, m, $2, d from tmp_insert on conflict (id) do update set message_id = excluded.message_id, channel_id = excluded.channel_id, data = excluded.data returning xmax".into()).await?;
                 super let stmt_upd_minmax = conn.prepare_static("update telegram.channel set min_message_id = $1, max_message_id = $2, last_fetch = now() at time zone 'UTC' where id = $3".into()).await?;
 
                 db::DBWrapper {
                     conn: &conn,
-                    stmts: [&stmt_get_range, &stmt_insert_msg, &stmt_upd_minmax],
+                    stmts: [&stmt_get_range, &stmt_upd_minmax],
                 }
             };
+            let (sink, limiter) = (&sink, &limiter);
             let futs = clients.iter().filter_map(|(id, client)| {
                 let channels = channels_by_id.remove(id)?;
                 let id = *id;
                 Some(async move {
                     let target = format!("telegram-fetch-message({id})");
                     for channel in channels {
-                        telegram::fetch_content(client, &channel, limit, &target, db).await;
+                        telegram::fetch_content(client, &channel, limit, &target, db, sink, limiter).await;
                     }
                 })
             });
             futures_util::future::join_all(futs).await;
         }
         Commands::Extract { save } => {
+            let mut conn = uscr::db::get_connection().await?;
             let map = db::get_searched_peers(&mut conn).await?;
 
             let mut inspector = extract::Inspector::new(
@@ -185,6 +219,7 @@ async fn main() -> anyhow::Result<()> {
             inspector.extract_content(&mut conn).await?;
         }
         Commands::Interact { peers: peers_filt } => {
+            let mut conn = uscr::db::get_connection().await?;
             let mut peers = db::get_all_bots_from_db(&mut conn).await?;
             {
                 let filt = peers_filt.into_iter().collect::<HashSet<i64>>();
@@ -202,17 +237,13 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
 
-            let stmt = conn.prepare_static("insert into telegram.interaction (bot_id, message_id, request, response) values ($1, $2, $3, $4) on conflict (bot_id, message_id) do update set request = excluded.request, response = excluded.response".into()).await?;
-            let db = db::DBWrapper {
-                conn: &conn,
-                stmts: [&stmt],
-            };
             let futs = clients.iter_mut().filter_map(|(id, client)|
                 Some(telegram::interact_bot_into_future(
                     client,
                     peers_by_id.remove(id)?,
                     format!("telegram-interact-bot({id})"),
-                    db,
+                    &sink,
+                    &limiter,
                 ))
             );
             futures_util::future::join_all(futs).await;
@@ -6,14 +6,10 @@ use grammers_session::{PackedChat, PackedType};
 use grammers_tl_types as tl;
 use hashbrown::{HashMap, HashSet};
 use parking_lot::Mutex;
-use tokio_postgres::Client as DBClient;
-use tokio_postgres::types::Json;
 use unicase::UniCase;
+use uscr::sink::{AnySink, Sink};
 
-use crate::{
-    db::DBWrapper,
-    telegram::{BotCommand, COMMAND_LIST, Channel, User, client::Client},
-};
+use crate::telegram::{BotCommand, COMMAND_LIST, Channel, Invite, User, client::Client, store_interaction};
 
 pub fn separate_id_and_names(
     raw: Vec<CompactString>,
@@ -43,22 +39,13 @@ pub fn separate_id_and_names(
 pub async fn work<'a, I>(
     keys: Vec<CompactString>,
     clients: I,
-    conn: &mut DBClient,
+    sink: &AnySink,
 ) -> (Vec<Channel>, Vec<User>)
 where
     I: Iterator<Item = (&'a i32, &'a Client)> + Send,
 {
-    const SQL_INVITE: &str = "insert into telegram.invite (hash, channel_id, type, description) values ($1, $2, $3, $4) on conflict (hash) do update set channel_id = excluded.channel_id, type = excluded.type, description = excluded.description";
-    const SQL_CMDS: &str = "insert into telegram.interaction (bot_id, message_id, request, response) values ($1, $2, $3, $4) on conflict (bot_id, message_id) do update set request = excluded.request, response = excluded.response";
-
     let keys = Mutex::new(keys);
-    let stmt_invite = conn.prepare_static(SQL_INVITE.into()).await.unwrap();
-    let stmt_cmds = conn.prepare_static(SQL_CMDS.into()).await.unwrap();
-    let db = DBWrapper {
-        conn,
-        stmts: [&stmt_invite, &stmt_cmds],
-    };
-    let futs = clients.map(|(id, client)| into_future(*id, client, &keys, db));
+    let futs = clients.map(|(id, client)| into_future(*id, client, &keys, sink));
     let folded = futures_util::future::join_all(futs).await;
 
     let (mut channels, mut users) = (Vec::new(), Vec::new());
@@ -145,7 +132,7 @@ async fn get_description(
 async fn access_channel(
     client: &Client,
     name: &str,
-    db: DBWrapper<'_, 2>,
+    sink: &AnySink,
     target: &str,
 ) -> anyhow::Result<Either<Channel, User>> {
     use grammers_client::types::Chat::{Channel as Chan, Group, User};
@@ -168,19 +155,19 @@ async fn access_channel(
     let PackedChat { id, access_hash, ty } = chat.pack();
     let (description, commands) = get_description(client, ty, id, access_hash.unwrap_or(0), target).await;
 
-    db.conn.execute(db.stmts[0], &[
-        &name,
-        &id,
-        &(match chat {
-            Chan(_) => b'C',
-            Group(_) => b'G',
-            User(_) => b'U',
-        }.cast_signed()),
-        &description,
-    ]).await?;
+    sink.write(&[Invite {
+        hash: name.into(),
+        channel_id: id,
+        kind: match chat {
+            Chan(_) => "C",
+            Group(_) => "G",
+            User(_) => "U",
+        },
+        description,
+    }]).await?;
 
     if let Some(commands) = commands {
-        db.conn.execute(db.stmts[1], &[&id, &-1i32, &COMMAND_LIST, &Json(commands)]).await?;
+        store_interaction(sink, id, -1, COMMAND_LIST, commands).await?;
     }
 
     let peer = Channel {
@@ -206,7 +193,7 @@ async fn access_channel(
 async fn access_invite(
     client: &Client,
     name: &str,
-    db: DBWrapper<'_, 2>,
+    sink: &AnySink,
     target: &str,
 ) -> anyhow::Result<Channel> {
     use tl::{
@@ -241,12 +228,7 @@ async fn access_invite(
     match chat {
         Chat::Channel(tl::types::Channel { id, access_hash, title, username, .. }) => {
             let description = get_description(client, PackedType::Megagroup, id, access_hash.unwrap_or(0), target).await.0;
-            db.conn
-                .execute(
-                    db.stmts[0],
-                    &[&name, &id, &(b'C'.cast_signed()), &description],
-                )
-                .await?;
+            sink.write(&[Invite { hash: name.into(), channel_id: id, kind: "C", description }]).await?;
 
             Ok(Channel {
                 id,
@@ -257,12 +239,7 @@ async fn access_invite(
         }
         Chat::Chat(tl::types::Chat { id, title, .. }) => {
             let description = get_description(client, PackedType::Chat, id, 0, target).await.0;
-            db.conn
-                .execute(
-                    db.stmts[0],
-                    &[&name, &id, &(b'G'.cast_signed()), &description],
-                )
-                .await?;
+            sink.write(&[Invite { hash: name.into(), channel_id: id, kind: "G", description }]).await?;
 
             Ok(Channel {
                 id,
@@ -279,7 +256,7 @@ async fn into_future(
     id: i32,
     client: &Client,
     keys: &Mutex<Vec<CompactString>>,
-    db: DBWrapper<'_, 2>,
+    sink: &AnySink,
 ) -> (Vec<Channel>, Vec<User>) {
     let mut channels = Vec::new();
     let mut users = Vec::new();
@@ -291,7 +268,7 @@ async fn into_future(
             return (channels, users);
         };
 
-        match access_channel(client, &key, db, &target_access_channel).await {
+        match access_channel(client, &key, sink, &target_access_channel).await {
             Ok(Left(mut channel)) => {
                 channel.app_id = id;
                 channels.push(channel);
@@ -305,7 +282,7 @@ async fn into_future(
             Err(e) => log::error!(target: &target_access_channel, "{e:?}"),
         }
 
-        match access_invite(client, &key, db, &target_access_invite).await {
+        match access_invite(client, &key, sink, &target_access_invite).await {
             Ok(mut channel) => {
                 channel.app_id = id;
                 channels.push(channel);
@@ -18,12 +18,14 @@ use grammers_client::InputMessage;
 use grammers_mtsender::{InvocationError, RpcError};
 use grammers_session::PackedChat;
 use grammers_tl_types as tl;
+use serde::Serialize;
 use tokio::{sync::oneshot, time::timeout};
-use tokio_postgres::types::Json;
+use tokio_postgres::types::{Json, Type};
 use types::Message;
 use uscr::{
-    db::{DBResult, ToSqlIter},
-    util::xmax_to_success,
+    db::bulk::Bulk,
+    scrape::limit::Limiter,
+    sink::{AnySink, Record, Sink},
 };
 
 use crate::db::DBWrapper;
@@ -34,9 +36,11 @@ pub fn parse_config(file: &Path) -> io::Result<Vec<InitConfig>> {
     serde_json::from_reader(reader).map_err(Into::into)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct User {
+    #[serde(flatten)]
     pub peer: Channel,
+    #[serde(skip)]
     pub hash_name: CompactString,
 }
 
@@ -57,7 +61,7 @@ impl From<Channel> for User {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Channel {
     pub id: i64,
     pub name: CompactString,
@@ -65,6 +69,100 @@ pub struct Channel {
     pub app_id: i32,
 }
 
+impl Record for Channel {
+    const TABLE: &'static str = "telegram.channel";
+    const KEY: &'static [&'static str] = &["id"];
+
+    /// A new channel has no message range yet, and is due for its first fetch.
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.id)
+            .column("name", Type::TEXT, |x| &*x.name)
+            .column("access_hash", Type::INT8, |x| x.access_hash)
+            .column("app_id", Type::INT4, |x| x.app_id)
+            .expr("min_message_id", "0")
+            .expr("max_message_id", "0")
+            .expr("last_fetch", "(now() at time zone 'UTC') - interval '1 day'")
+            .on_conflict_update(&["id"], &["name", "access_hash", "app_id"])
+    }
+}
+
+impl Record for User {
+    const TABLE: &'static str = "telegram.bots";
+    const KEY: &'static [&'static str] = &["id"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("id", Type::INT8, |x| x.peer.id)
+            .column("name", Type::TEXT, |x| &*x.peer.name)
+            .column("access_hash", Type::INT8, |x| x.peer.access_hash)
+            .column("app_id", Type::INT4, |x| x.peer.app_id)
+            .on_conflict_update(&["id"], &["name", "access_hash", "app_id"])
+    }
+}
+
+/// A username or invite hash resolved by `ping`.
+#[derive(Debug, Serialize)]
+pub struct Invite {
+    pub hash: CompactString,
+    pub channel_id: i64,
+    /// `C` (channel), `G` (group) or `U` (user).
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub description: String,
+}
+
+impl Record for Invite {
+    const TABLE: &'static str = "telegram.invite";
+    const KEY: &'static [&'static str] = &["hash"];
+
+    /// `type` is a `"char"` column, which text is assigned to.
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("hash", Type::TEXT, |x| &*x.hash)
+            .column("channel_id", Type::INT8, |x| x.channel_id)
+            .column("type", Type::TEXT, |x| x.kind)
+            .column("description", Type::TEXT, |x| &*x.description)
+            .on_conflict_update(&["hash"], &["channel_id", "type", "description"])
+    }
+}
+
+/// The answer of a bot to `request`, or its command list (message `-1`).
+#[derive(Debug, Serialize)]
+pub struct Interaction {
+    pub bot_id: i64,
+    pub message_id: i32,
+    pub request: &'static str,
+    pub response: serde_json::Value,
+}
+
+impl Record for Interaction {
+    const TABLE: &'static str = "telegram.interaction";
+    const KEY: &'static [&'static str] = &["bot_id", "message_id"];
+
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("bot_id", Type::INT8, |x| x.bot_id)
+            .column("message_id", Type::INT4, |x| x.message_id)
+            .column("request", Type::TEXT, |x| x.request)
+            .column("response", Type::JSONB, |x| Json(&x.response))
+            .on_conflict_update(&["bot_id", "message_id"], &["request", "response"])
+    }
+}
+
+/// Writes `response` to `request` of the bot `bot_id` through `sink`.
+pub async fn store_interaction<T: Serialize>(
+    sink: &AnySink,
+    bot_id: i64,
+    message_id: i32,
+    request: &'static str,
+    response: T,
+) -> anyhow::Result<()> {
+    let response = serde_json::to_value(response)?;
+    sink.write(&[Interaction { bot_id, message_id, request, response }]).await?;
+    Ok(())
+}
+
 pub async fn fetch_channels_by_id<C>(
     client: &Client,
     channels: C,
@@ -100,39 +198,54 @@ where
         .collect())
 }
 
+#[derive(Debug, Serialize)]
+pub struct StoredMessage {
+    pub message_id: i32,
+    pub channel_id: i64,
+    pub data: Message,
+}
+
+impl Record for StoredMessage {
+    const TABLE: &'static str = "telegram.message";
+    const KEY: &'static [&'static str] = &["channel_id", "message_id"];
+
+    /// `id` packs the channel into the high 32 bits so that message ids stay unique.
+    fn bulk(rows: &[Self]) -> Bulk<'_, Self> {
+        Bulk::new(Self::TABLE, rows)
+            .column("message_id", Type::INT4, |x| x.message_id)
+            .column("channel_id", Type::INT8, |x| x.channel_id)
+            .column("data", Type::JSONB, |x| Json(&x.data))
+            .expr("id", "(\"channel_id\" << 32) | \"message_id\"")
+            .on_conflict_update(&["id"], &["message_id", "channel_id", "data"])
+    }
+}
+
 async fn insert_to_db(
-    messages: &[(i32, Message)],
+    messages: &[StoredMessage],
     channel_id: i64,
     interval: &mut Option<(i32, i32)>,
     target: &str,
-    db: DBWrapper<'_, 3>,
+    db: DBWrapper<'_, 2>,
+    sink: &AnySink,
 ) -> Option<i32> {
     async fn insert_to_db_inner(
-        messages: &[(i32, Message)],
-        channel_id: i64,
-        db: DBWrapper<'_, 3>,
-    ) -> DBResult<(usize, usize, i32, i32)> {
+        messages: &[StoredMessage],
+        sink: &AnySink,
+    ) -> anyhow::Result<(usize, usize, i32, i32)> {
         let len = messages.len();
-        let min = messages.iter().fold(i32::MAX, |x, y| x.min(y.0));
-        let max = messages.iter().fold(i32::MIN, |x, y| x.max(y.0));
-
-        let rows = db
-            .conn
-            .query(db.stmts[1], &[
-                &ToSqlIter(messages.iter().map(|x| x.0)),
-                &channel_id,
-                &ToSqlIter(messages.iter().map(|x| Json(&x.1))),
-            ])
-            .await?;
-
-        Ok((xmax_to_success(rows.iter()), len, min, max))
+        let min = messages.iter().fold(i32::MAX, |x, y| x.min(y.message_id));
+        let max = messages.iter().fold(i32::MIN, |x, y| x.max(y.message_id));
+
+        let upserted = sink.write(messages).await?;
+
+        Ok((upserted.inserted, len, min, max))
     }
 
     if messages.is_empty() {
         log::warn!(target: target, "empty batch");
         None
     } else {
-        match insert_to_db_inner(messages, channel_id, db).await {
+        match insert_to_db_inner(messages, sink).await {
             Ok((succ, len, min, max)) => {
                 log::info!(target: target, "{succ}/{len} data upserted, id range: [{min}, {max}]");
                 let inner = match interval {
@@ -143,7 +256,7 @@ async fn insert_to_db(
                     }
                     None => interval.insert((min, max)),
                 };
-                if let Err(e) = db.conn.execute(db.stmts[2], &[&inner.0, &inner.1, &channel_id]).await {
+                if let Err(e) = db.conn.execute(db.stmts[1], &[&inner.0, &inner.1, &channel_id]).await {
                     log::error!(target: target, "{e:#?}");
                 }
                 Some(max)
@@ -161,7 +274,9 @@ pub async fn fetch_content(
     channel: &Channel,
     limit: u32,
     target: &str,
-    db: DBWrapper<'_, 3>,
+    db: DBWrapper<'_, 2>,
+    sink: &AnySink,
+    limiter: &Limiter,
 ) {
     log::info!(target: target, "======== \x1b[32mFETCHING CONTENT \x1b[36m{}\x1b[0m ========", channel.id);
 
@@ -189,6 +304,7 @@ pub async fn fetch_content(
     let mut jumping = interval
         .is_some_and(|(min, max)| min > 1 && limit > 1 && (max - min).cast_unsigned() < limit - 1);
 
+    let key = format!("telegram-content#{}", channel.app_id);
     let mut iter = client.inner.iter_messages(packed);
     let mut buffer = Vec::with_capacity(100);
     'outer: loop {
@@ -199,9 +315,9 @@ pub async fn fetch_content(
             } else {
                 #[rustfmt::skip]
                 if !buffer.is_empty() {
-                    let sleep = tokio::time::sleep(const { core::time::Duration::from_millis(180) });
-                    let db_fut = insert_to_db(&buffer, channel.id, &mut interval, target, db);
-                    let batch_max: Option<i32> = join!(sleep, db_fut).await.1;
+                    let pace = limiter.wait(&key);
+                    let db_fut = insert_to_db(&buffer, channel.id, &mut interval, target, db, sink);
+                    let batch_max: Option<i32> = join!(pace, db_fut).await.1;
                     let (l, r) = interval.expect("interval shouldn't be None after insert");
                     let l_i = r.cast_unsigned().saturating_sub(limit).cast_signed();
                     stop_point = stop_point.max(l_i);
@@ -221,7 +337,7 @@ pub async fn fetch_content(
                 Ok(item) => break item,
                 Err(InvocationError::Rpc(RpcError { code: 400, name, caused_by, value })) => {
                     log::error!(target: target, "channel error: {name} caused by \x1b[33m{caused_by:?}\x1b[0m, with value \x1b[33m{value:?}\x1b[0m");
-                    insert_to_db(&buffer, channel.id, &mut interval, target, db).await;
+                    insert_to_db(&buffer, channel.id, &mut interval, target, db, sink).await;
                     break 'outer;
                 }
                 Err(e) => {
@@ -229,7 +345,7 @@ pub async fn fetch_content(
                     n_err += 1;
                     if n_err == 5 {
                         log::error!(target: target, "channel error too many times: {e:#?}, breaking");
-                        insert_to_db(&buffer, channel.id, &mut interval, target, db).await;
+                        insert_to_db(&buffer, channel.id, &mut interval, target, db, sink).await;
                         break 'outer;
                     }
                     tokio::time::sleep(const { core::time::Duration::from_secs(1) }).await;
@@ -237,10 +353,14 @@ pub async fn fetch_content(
             }
         };
         let Some(message) = item else {
-            insert_to_db(&buffer, channel.id, &mut interval, target, db).await;
+            insert_to_db(&buffer, channel.id, &mut interval, target, db, sink).await;
             break;
         };
-        buffer.push((message.raw.id, message.raw.into()));
+        buffer.push(StoredMessage {
+            message_id: message.raw.id,
+            channel_id: channel.id,
+            data: message.raw.into(),
+        });
     }
 
     log::info!(target: target, "span update (of {}): {:?} => {:?}", channel.id, interval_origin, interval);
@@ -280,12 +400,10 @@ async fn interact_bot(
     client: &Client,
     bot: &User,
     target: &str,
-    db: DBWrapper<'_, 1>,
-    pre_sleep: Option<Duration>,
+    sink: &AnySink,
+    limiter: &Limiter,
 ) {
-    if let Some(duration) = pre_sleep {
-        tokio::time::sleep(duration).await;
-    }
+    limiter.wait(&format!("telegram-bot#{}", bot.peer.app_id)).await;
 
     log::info!(target: target, "======== \x1b[1;34mINTERACTING BOT \x1b[36m{}\x1b[0m ========", bot.peer.id);
 /*
@@ -308,7 +426,7 @@ async fn interact_bot(
         Ok(EUUserFull::Full(TUUserFull { full_user: EUserFull::Full(u), .. })) => {
             if let Some(EBotInfo::Info(TBotInfo { commands: Some(commands), .. })) = u.bot_info {
                 let commands = commands.into_iter().map(Into::into).collect::<Vec<BotCommand>>();
-                if let Err(e) = db.conn.execute(db.stmts[0], &[&bot.peer.id, &-1i32, &COMMAND_LIST, &Json(commands)]).await {
+                if let Err(e) = store_interaction(sink, bot.peer.id, -1, COMMAND_LIST, commands).await {
                     log::error!(target: target, "db(insert <command list>): {e:?}");
                 }
             } else {
@@ -326,14 +444,14 @@ async fn interact_bot(
 
     if let Some(resp_start) = interact_inner(client, packed, "/start", target).await {
         let id = resp_start.id;
-        if let Err(e) = db.conn.execute(db.stmts[0], &[&bot.peer.id, &id, &"/start", &Json(resp_start)]).await {
+        if let Err(e) = store_interaction(sink, bot.peer.id, id, "/start", resp_start).await {
             log::error!(target: target, "db(insert /start): {e:?}");
         }
     }
 
     if let Some(resp_help) = interact_inner(client, packed, "/help", target).await {
         let id = resp_help.id;
-        if let Err(e) = db.conn.execute(db.stmts[0], &[&bot.peer.id, &id, &"/help", &Json(resp_help)]).await {
+        if let Err(e) = store_interaction(sink, bot.peer.id, id, "/help", resp_help).await {
             log::error!(target: target, "db(insert /help): {e:?}");
         }
     }
@@ -343,18 +461,14 @@ pub async fn interact_bot_into_future(
     client: &mut Client,
     bots: Vec<User>,
     target: String,
-    db: DBWrapper<'_, 1>,
+    sink: &AnySink,
+    limiter: &Limiter,
 ) {
-    const DIF: Duration = Duration::from_millis(3141);
-
     client.start_listen();
 
     let futs = FuturesUnordered::new();
-
-    let mut acc = Duration::default();
     for bot in &bots {
-        futs.push(interact_bot(client, bot, &target, db, Some(acc)));
-        acc += DIF;
+        futs.push(interact_bot(client, bot, &target, sink, limiter));
     }
 
     futs.collect::<()>().await;
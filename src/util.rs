@@ -37,6 +37,48 @@ pub fn simple_parse(mut time: &[Char]) -> Option<SystemTime> {
     date.ok()
 }
 
+/// 64-bit FNV-1a of the UTF-8 bytes of `s`, as a signed id.
+///
+/// Used for ids derived from scraped text (accsmarket listings, smmrapid
+/// categories). Unlike [`std::hash::DefaultHasher`], whose output may change
+/// between Rust releases, this is fixed forever: changing it splits the history
+/// of every such row, and stored rows must then be re-keyed (see `rekey`).
+#[must_use]
+pub const fn stable_id(s: &str) -> i64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let bytes = s.as_bytes();
+    let mut hash = OFFSET;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(PRIME);
+        i += 1;
+    }
+    hash.cast_signed()
+}
+
+/// `YYYY-MM-DDThh:mm:ssZ`, as used by WARC.
+#[must_use]
+pub fn iso8601(time: SystemTime) -> String {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = ((secs / 86400).cast_signed(), secs % 86400);
+
+    // civil_from_days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+
+    format!("{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z", rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
 pub fn xmax_to_success<'a, I>(rows: I) -> usize
 where
     I: Iterator<Item = &'a tokio_postgres::Row>,